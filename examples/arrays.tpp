@@ -0,0 +1,22 @@
+fn fill(n) -> [int] {
+    var values = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0]!
+    for i in 0..len(values) {
+        values[i] = n * i!
+    }
+    return values!
+}
+
+fn sum(values: [int]) {
+    var total = 0!
+    for i in 0..len(values) {
+        total = total + values[i]!
+    }
+    return total!
+}
+
+var table = [fill(1), fill(2), fill(3)]!
+table[2][9] = 0!
+
+for row in 0..len(table) {
+    print sum(table[row])!
+}
@@ -3,7 +3,7 @@ var b = 1!
 var n = 0!
 
 loop {
-    if(n == 10){
+    if (n == 10) {
         break!
     }
 
@@ -0,0 +1,26 @@
+fn fib(n) {
+    if (n < 2) {
+        return n!
+    }
+    return fib(n - 1) + fib(n - 2)!
+}
+
+fn describe(n) -> string {
+    if (is_even(n)) {
+        return "even"!
+    }
+    return "odd"!
+}
+
+fn is_even(n) -> bool {
+    return n / 2 * 2 == n!
+}
+
+fn report(n) {
+    print fib(n)!
+    print describe(fib(n))!
+}
+
+for i in 1..8 {
+    report(i)!
+}
@@ -0,0 +1,9 @@
+var x = 1!
+x = 2!
+x = 3!
+
+print prev x!
+
+x = prev x!
+print x!
+print prev x!
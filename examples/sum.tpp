@@ -0,0 +1,9 @@
+print "How many numbers?"!
+var count = read_int()!
+
+var total = 0!
+for i in 0..count {
+    total = total + read_int()!
+}
+
+print total!
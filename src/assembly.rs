@@ -0,0 +1,810 @@
+use crate::ast::{
+    Ast, BinaryOperator, Expression, ExpressionKind, Statement, StatementKind, Type, UnaryOperator,
+};
+
+// Runtime routines, written by hand and called like C functions. They align
+// the stack themselves, because the generated code keeps temporaries on it
+// and calls them at any alignment
+
+const PRINT_INT: &str = "    .section .rodata
+print_int_format__rt:
+    .string \"%lld\\n\"
+    .text
+print_int__rt:
+    pushq %rbp
+    movq %rsp, %rbp
+    andq $-16, %rsp
+    movq %rdi, %rsi
+    leaq print_int_format__rt(%rip), %rdi
+    xorl %eax, %eax
+    call printf@PLT
+    leave
+    ret
+";
+
+const PRINT_BOOL: &str = "    .section .rodata
+true__rt:
+    .string \"true\"
+false__rt:
+    .string \"false\"
+    .text
+print_bool__rt:
+    pushq %rbp
+    movq %rsp, %rbp
+    andq $-16, %rsp
+    testq %rdi, %rdi
+    leaq false__rt(%rip), %rdi
+    leaq true__rt(%rip), %rax
+    cmovneq %rax, %rdi
+    call puts@PLT
+    leave
+    ret
+";
+
+const PRINT_STRING: &str = "    .text
+print_string__rt:
+    pushq %rbp
+    movq %rsp, %rbp
+    andq $-16, %rsp
+    call puts@PLT
+    leave
+    ret
+";
+
+// Returns 1 when the strings are equal and 0 otherwise
+const STRING_EQUAL: &str = "    .text
+string_equal__rt:
+    pushq %rbp
+    movq %rsp, %rbp
+    andq $-16, %rsp
+    call strcmp@PLT
+    testl %eax, %eax
+    sete %al
+    movzbl %al, %eax
+    leave
+    ret
+";
+
+// Zero values of strings and arrays
+const EMPTY_STRING: &str = "    .section .rodata
+empty_string__rt:
+    .string \"\"
+";
+
+const EMPTY_ARRAY: &str = "    .section .rodata
+    .p2align 3
+empty_array__rt:
+    .quad 0
+";
+
+// Arrays are a quadword with the length followed by the elements. They live
+// on the heap and are never freed, so they can be returned from functions.
+// The caller fills in the elements
+const ARRAY_NEW: &str = "    .text
+array_new__rt:
+    pushq %rbp
+    movq %rsp, %rbp
+    subq $16, %rsp
+    andq $-16, %rsp
+    movq %rdi, -8(%rbp)
+    leaq 8(,%rdi,8), %rdi
+    call malloc@PLT
+    movq -8(%rbp), %rcx
+    movq %rcx, (%rax)
+    leave
+    ret
+";
+
+// Returns the address of the element at the index in %rsi of the array in
+// %rdi, or stops the program when it is out of bounds. %rdx holds the line
+const INDEX: &str = "    .section .rodata
+index_error__rt:
+    .string \"error: index %lld is out of bounds for an array of length %lld on line %d\\n\"
+    .text
+index__rt:
+    cmpq (%rdi), %rsi
+    jae .Lindex_error__rt
+    leaq 8(%rdi,%rsi,8), %rax
+    ret
+.Lindex_error__rt:
+    pushq %rbp
+    movq %rsp, %rbp
+    subq $32, %rsp
+    andq $-16, %rsp
+    movq %rdi, -8(%rbp)
+    movq %rsi, -16(%rbp)
+    movq %rdx, -24(%rbp)
+    movq stdout@GOTPCREL(%rip), %rax
+    movq (%rax), %rdi
+    call fflush@PLT
+    movq stderr@GOTPCREL(%rip), %rax
+    movq (%rax), %rdi
+    leaq index_error__rt(%rip), %rsi
+    movq -16(%rbp), %rdx
+    movq -8(%rbp), %rax
+    movq (%rax), %rcx
+    movq -24(%rbp), %r8
+    xorl %eax, %eax
+    call fprintf@PLT
+    movl $1, %edi
+    call exit@PLT
+";
+
+// Reads a line of any length from standard input, without the line break.
+// Returns the line in %rax, and 1 in %rdx when standard input ended before
+// anything could be read, in which case the line is empty
+const READ_LINE: &str = "    .text
+read_line__rt:
+    pushq %rbp
+    movq %rsp, %rbp
+    subq $16, %rsp
+    andq $-16, %rsp
+    movq $0, -8(%rbp)
+    movq $0, -16(%rbp)
+    leaq -8(%rbp), %rdi
+    leaq -16(%rbp), %rsi
+    movq stdin@GOTPCREL(%rip), %rax
+    movq (%rax), %rdx
+    call getline@PLT
+    testq %rax, %rax
+    js .Lread_line_ended__rt
+    movq -8(%rbp), %rcx
+    cmpb $10, -1(%rcx,%rax)
+    jne .Lread_line_return__rt
+    decq %rax
+    movb $0, (%rcx,%rax)
+    testq %rax, %rax
+    jz .Lread_line_return__rt
+    cmpb $13, -1(%rcx,%rax)
+    jne .Lread_line_return__rt
+    decq %rax
+    movb $0, (%rcx,%rax)
+.Lread_line_return__rt:
+    movq %rcx, %rax
+    xorl %edx, %edx
+    leave
+    ret
+.Lread_line_ended__rt:
+    leaq empty_string__rt(%rip), %rax
+    movl $1, %edx
+    leave
+    ret
+";
+
+// Reads an integer on a line of its own, or stops the program when the line
+// is not an integer or there is nothing left to read. %rdi holds the line
+const READ_INT: &str = "    .section .rodata
+read_int_ended__rt:
+    .string \"error: expected an integer but standard input ended on line %d\\n\"
+read_int_error__rt:
+    .string \"error: expected an integer but read \\\"%s\\\" on line %d\\n\"
+    .text
+read_int__rt:
+    pushq %rbp
+    movq %rsp, %rbp
+    subq $32, %rsp
+    andq $-16, %rsp
+    movq %rdi, -8(%rbp)
+    call read_line__rt
+    movq %rax, -16(%rbp)
+    testq %rdx, %rdx
+    jnz .Lread_int_ended__rt
+    call __errno_location@PLT
+    movl $0, (%rax)
+    movq -16(%rbp), %rdi
+    leaq -24(%rbp), %rsi
+    movl $10, %edx
+    call strtoll@PLT
+    movq %rax, -32(%rbp)
+.Lread_int_space__rt:
+    movq -24(%rbp), %rax
+    movzbl (%rax), %edi
+    call isspace@PLT
+    testl %eax, %eax
+    jz .Lread_int_check__rt
+    incq -24(%rbp)
+    jmp .Lread_int_space__rt
+.Lread_int_check__rt:
+    movq -24(%rbp), %rax
+    cmpq -16(%rbp), %rax
+    je .Lread_int_error__rt
+    cmpb $0, (%rax)
+    jne .Lread_int_error__rt
+    call __errno_location@PLT
+    cmpl $34, (%rax)
+    je .Lread_int_error__rt
+    movq -16(%rbp), %rdi
+    call free@PLT
+    movq -32(%rbp), %rax
+    leave
+    ret
+.Lread_int_ended__rt:
+    movq stdout@GOTPCREL(%rip), %rax
+    movq (%rax), %rdi
+    call fflush@PLT
+    movq stderr@GOTPCREL(%rip), %rax
+    movq (%rax), %rdi
+    leaq read_int_ended__rt(%rip), %rsi
+    movq -8(%rbp), %rdx
+    xorl %eax, %eax
+    call fprintf@PLT
+    movl $1, %edi
+    call exit@PLT
+.Lread_int_error__rt:
+    movq stdout@GOTPCREL(%rip), %rax
+    movq (%rax), %rdi
+    call fflush@PLT
+    movq stderr@GOTPCREL(%rip), %rax
+    movq (%rax), %rdi
+    leaq read_int_error__rt(%rip), %rsi
+    movq -16(%rbp), %rdx
+    movq -8(%rbp), %rcx
+    xorl %eax, %eax
+    call fprintf@PLT
+    movl $1, %edi
+    call exit@PLT
+";
+
+// Every runtime routine with the routines it relies on
+const RUNTIME: &[(&str, &[&str], &str)] = &[
+    ("print_int__rt", &[], PRINT_INT),
+    ("print_bool__rt", &[], PRINT_BOOL),
+    ("print_string__rt", &[], PRINT_STRING),
+    ("string_equal__rt", &[], STRING_EQUAL),
+    ("empty_string__rt", &[], EMPTY_STRING),
+    ("empty_array__rt", &[], EMPTY_ARRAY),
+    ("array_new__rt", &[], ARRAY_NEW),
+    ("index__rt", &[], INDEX),
+    ("read_line__rt", &["empty_string__rt"], READ_LINE),
+    ("read_int__rt", &["read_line__rt"], READ_INT),
+];
+
+// Every variable takes two quadwords on the stack or in the data section,
+// its value followed by its previous value
+const SLOT_SIZE: usize = 16;
+
+// Lowers the ast to x86-64 assembly for the GNU assembler, to be linked with
+// the C library. Every value is a quadword: integers, booleans as 0 or 1,
+// and pointers to strings and arrays. Expressions leave their result in
+// %rax and keep intermediate results on the stack
+#[derive(Default)]
+pub struct AssemblyGenerator {
+    functions: Vec<String>,
+    // Top level variables, zeroed until main reaches their declaration
+    globals: Vec<String>,
+    // String literals by their index
+    strings: Vec<String>,
+    // Names of the runtime routines the program uses
+    runtime: Vec<&'static str>,
+    // Instructions of the function that is being generated
+    code: String,
+    // Number of variables in its stack frame
+    slots: usize,
+    // Local variables that are in scope and their slots
+    scope: Vec<(String, usize)>,
+    // Label, continue target and break target of the loops around the code
+    loops: Vec<(Option<String>, String, String)>,
+    label_count: usize,
+}
+
+impl AssemblyGenerator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn generate(mut self, ast: &Ast) -> String {
+        for statement in ast.statements.iter() {
+            if let StatementKind::VariableDeclaration(name, value) = &statement.kind {
+                let zero = match value.ty() {
+                    Type::String => self.require("empty_string__rt"),
+                    Type::Array(_) => self.require("empty_array__rt"),
+                    Type::Int | Type::Bool => "0",
+                };
+                self.globals.push(format!(
+                    "{}__var:\n    .quad {}\n    .quad {}\n",
+                    name, zero, zero
+                ));
+            }
+        }
+
+        for statement in ast.statements.iter() {
+            match &statement.kind {
+                StatementKind::FunctionDeclaration(..) => {}
+                StatementKind::VariableDeclaration(name, value) => {
+                    self.expression(value);
+                    self.initialize(Self::global(name));
+                }
+                _ => self.statement(statement),
+            }
+        }
+        self.emit("xorl %eax, %eax");
+        self.emit("leave");
+        self.emit("ret");
+        self.finish_function("main");
+
+        for statement in ast.statements.iter() {
+            if let StatementKind::FunctionDeclaration(name, parameters, _, statements) =
+                &statement.kind
+            {
+                // The caller pushes the arguments from left to right, so the
+                // last one is right above the return address
+                for (i, (parameter, _)) in parameters.iter().enumerate() {
+                    let slot = self.declare(parameter);
+                    let offset = 16 + 8 * (parameters.len() - 1 - i);
+                    self.emit(&format!("movq {}(%rbp), %rax", offset));
+                    self.initialize(Self::local(slot));
+                }
+
+                self.block(statements);
+                self.emit("leave");
+                self.emit("ret");
+                self.finish_function(&format!("{}__fn", name));
+            }
+        }
+
+        self.render()
+    }
+
+    fn render(&self) -> String {
+        let mut assembly = String::from("    .text\n    .globl main\n");
+        for function in self.functions.iter() {
+            assembly += function;
+        }
+
+        for (name, _, code) in RUNTIME.iter() {
+            if self.runtime.contains(name) {
+                assembly += code;
+            }
+        }
+
+        if !self.strings.is_empty() {
+            assembly += "    .section .rodata\n";
+            for (i, string) in self.strings.iter().enumerate() {
+                assembly += &format!("string_{}__rt:\n    .string {}\n", i, string);
+            }
+        }
+
+        if !self.globals.is_empty() {
+            assembly += "    .data\n    .p2align 3\n";
+            for global in self.globals.iter() {
+                assembly += global;
+            }
+        }
+
+        // Tells the linker that the program does not need an executable stack
+        assembly += "    .section .note.GNU-stack,\"\",@progbits\n";
+        assembly
+    }
+
+    fn emit(&mut self, instruction: &str) {
+        self.code += &format!("    {}\n", instruction);
+    }
+
+    fn place_label(&mut self, label: &str) {
+        self.code += &format!("{}:\n", label);
+    }
+
+    fn new_label(&mut self) -> String {
+        self.label_count += 1;
+        format!(".L{}", self.label_count)
+    }
+
+    // Makes the routine and the ones it relies on part of the program and
+    // returns its name
+    fn require(&mut self, routine: &'static str) -> &'static str {
+        if !self.runtime.contains(&routine) {
+            self.runtime.push(routine);
+
+            let (_, dependencies, _) = RUNTIME
+                .iter()
+                .find(|(name, _, _)| *name == routine)
+                .expect("unknown runtime routine");
+            for dependency in dependencies.iter() {
+                self.require(dependency);
+            }
+        }
+
+        routine
+    }
+
+    fn call_runtime(&mut self, routine: &'static str) {
+        self.require(routine);
+        self.emit(&format!("call {}", routine));
+    }
+
+    // Puts the prologue in front of the instructions, now that the size of
+    // the stack frame is known
+    fn finish_function(&mut self, name: &str) {
+        let mut function = format!("{}:\n    pushq %rbp\n    movq %rsp, %rbp\n", name);
+        if self.slots > 0 {
+            function += &format!("    subq ${}, %rsp\n", self.slots * SLOT_SIZE);
+        }
+        function += &std::mem::take(&mut self.code);
+
+        self.functions.push(function);
+        self.slots = 0;
+        self.scope.clear();
+    }
+
+    // A new variable slot in the stack frame that is not in scope by name
+    fn slot(&mut self) -> usize {
+        self.slots += 1;
+        self.slots - 1
+    }
+
+    fn declare(&mut self, name: &str) -> usize {
+        let slot = self.slot();
+        self.scope.push((name.to_string(), slot));
+        slot
+    }
+
+    // Operands for the value and the previous value of a variable
+    fn local(slot: usize) -> (String, String) {
+        let offset = slot * SLOT_SIZE;
+        (
+            format!("-{}(%rbp)", offset + 16),
+            format!("-{}(%rbp)", offset + 8),
+        )
+    }
+
+    fn global(name: &str) -> (String, String) {
+        (
+            format!("{}__var(%rip)", name),
+            format!("{}__var+8(%rip)", name),
+        )
+    }
+
+    fn variable(&self, name: &str) -> (String, String) {
+        match self
+            .scope
+            .iter()
+            .rev()
+            .find(|(variable, _)| variable == name)
+        {
+            Some((_, slot)) => Self::local(*slot),
+            None => Self::global(name),
+        }
+    }
+
+    // Stores %rax as the value and the previous value of a new variable
+    fn initialize(&mut self, (value, previous): (String, String)) {
+        self.emit(&format!("movq %rax, {}", value));
+        self.emit(&format!("movq %rax, {}", previous));
+    }
+
+    // Stores %rax in a variable and keeps its old value as the previous one
+    fn assign(&mut self, (value, previous): (String, String)) {
+        self.emit(&format!("movq {}, %rcx", value));
+        self.emit(&format!("movq %rcx, {}", previous));
+        self.emit(&format!("movq %rax, {}", value));
+    }
+
+    // Generates the statements in a new scope
+    fn block(&mut self, statements: &[Statement]) {
+        let scope = self.scope.len();
+        for statement in statements.iter() {
+            self.statement(statement);
+        }
+        self.scope.truncate(scope);
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        let line = statement.span.line;
+        match &statement.kind {
+            StatementKind::VariableDeclaration(name, value) => {
+                self.expression(value);
+                let slot = self.declare(name);
+                self.initialize(Self::local(slot));
+            }
+            StatementKind::Assignment(name, value) => {
+                self.expression(value);
+                self.assign(self.variable(name));
+            }
+            StatementKind::IndexAssignment(name, indices, value) => {
+                let (array, _) = self.variable(name);
+                self.emit(&format!("movq {}, %rax", array));
+                for (i, index) in indices.iter().enumerate() {
+                    self.element_address(index, line);
+                    if i + 1 < indices.len() {
+                        self.emit("movq (%rax), %rax");
+                    }
+                }
+
+                self.emit("pushq %rax");
+                self.expression(value);
+                self.emit("popq %rcx");
+                self.emit("movq %rax, (%rcx)");
+            }
+            StatementKind::PrintStatement(value) => {
+                self.expression(value);
+                self.emit("movq %rax, %rdi");
+                match value.ty() {
+                    Type::Int => self.call_runtime("print_int__rt"),
+                    Type::Bool => self.call_runtime("print_bool__rt"),
+                    Type::String => self.call_runtime("print_string__rt"),
+                    Type::Array(_) => unreachable!("the type checker rejects printing arrays"),
+                }
+            }
+            StatementKind::ExpressionStatement(value) => self.expression(value),
+            StatementKind::IfStatement(condition, statements, else_statements) => {
+                let else_label = self.new_label();
+                self.expression(condition);
+                self.emit("testq %rax, %rax");
+                self.emit(&format!("jz {}", else_label));
+                self.block(statements);
+
+                match else_statements {
+                    Some(else_statements) => {
+                        let end_label = self.new_label();
+                        self.emit(&format!("jmp {}", end_label));
+                        self.place_label(&else_label);
+                        self.block(else_statements);
+                        self.place_label(&end_label);
+                    }
+                    None => self.place_label(&else_label),
+                }
+            }
+            StatementKind::LoopStatement(..)
+            | StatementKind::WhileStatement(..)
+            | StatementKind::ForStatement(..) => self.loop_statement(statement, None),
+            StatementKind::LabeledStatement(label, statement) => {
+                self.loop_statement(statement, Some(label))
+            }
+            StatementKind::BreakStatement(label) => {
+                let (_, _, break_label) = self.target(label);
+                let jump = format!("jmp {}", break_label);
+                self.emit(&jump);
+            }
+            StatementKind::ContinueStatement(label) => {
+                let (_, continue_label, _) = self.target(label);
+                let jump = format!("jmp {}", continue_label);
+                self.emit(&jump);
+            }
+            StatementKind::ReturnStatement(value) => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+                self.emit("leave");
+                self.emit("ret");
+            }
+            StatementKind::FunctionDeclaration(..) => {
+                unreachable!("functions are only declared at the top level")
+            }
+        }
+    }
+
+    // The loop a break or continue with this label jumps out of
+    fn target(&self, label: &Option<String>) -> &(Option<String>, String, String) {
+        match label {
+            Some(label) => self
+                .loops
+                .iter()
+                .rev()
+                .find(|(target, _, _)| target.as_ref() == Some(label)),
+            None => self.loops.last(),
+        }
+        .expect("semantic analysis checks that breaks and continues are inside a loop")
+    }
+
+    // Generates a loop
+    fn loop_statement(&mut self, statement: &Statement, label: Option<&String>) {
+        let scope = self.scope.len();
+        let start_label = self.new_label();
+        let continue_label = self.new_label();
+        let break_label = self.new_label();
+
+        match &statement.kind {
+            StatementKind::LoopStatement(statements) => {
+                self.place_label(&start_label);
+                self.place_label(&continue_label);
+                self.loop_body(statements, label, &continue_label, &break_label);
+            }
+            StatementKind::WhileStatement(condition, statements) => {
+                self.place_label(&start_label);
+                self.place_label(&continue_label);
+                self.expression(condition);
+                self.emit("testq %rax, %rax");
+                self.emit(&format!("jz {}", break_label));
+                self.loop_body(statements, label, &continue_label, &break_label);
+            }
+            StatementKind::ForStatement(name, start, end, statements) => {
+                self.expression(start);
+                let counter = self.declare(name);
+                self.initialize(Self::local(counter));
+                self.expression(end);
+                let end = self.slot();
+                let (end, _) = Self::local(end);
+                self.emit(&format!("movq %rax, {}", end));
+
+                let (value, previous) = Self::local(counter);
+                self.place_label(&start_label);
+                self.emit(&format!("movq {}, %rax", value));
+                self.emit(&format!("cmpq {}, %rax", end));
+                self.emit(&format!("jge {}", break_label));
+                self.loop_body(statements, label, &continue_label, &break_label);
+
+                self.place_label(&continue_label);
+                self.emit(&format!("movq {}, %rax", value));
+                self.emit(&format!("movq %rax, {}", previous));
+                self.emit(&format!("incq {}", value));
+            }
+            _ => unreachable!("only loops can be labeled"),
+        }
+
+        self.emit(&format!("jmp {}", start_label));
+        self.place_label(&break_label);
+        self.scope.truncate(scope);
+    }
+
+    fn loop_body(
+        &mut self,
+        statements: &[Statement],
+        label: Option<&String>,
+        continue_label: &str,
+        break_label: &str,
+    ) {
+        self.loops.push((
+            label.cloned(),
+            continue_label.to_string(),
+            break_label.to_string(),
+        ));
+        self.block(statements);
+        self.loops.pop();
+    }
+
+    // Turns the array in %rax into the address of the element at the index
+    fn element_address(&mut self, index: &Expression, line: usize) {
+        self.emit("pushq %rax");
+        self.expression(index);
+        self.emit("movq %rax, %rsi");
+        self.emit("popq %rdi");
+        self.emit(&format!("movq ${}, %rdx", line));
+        self.call_runtime("index__rt");
+    }
+
+    // Escapes a string for the .string directive. Anything but printable
+    // ASCII is written as octal escapes of its UTF-8 bytes
+    fn string_literal(value: &str) -> String {
+        let mut literal = String::from("\"");
+        for byte in value.bytes() {
+            match byte {
+                b'"' => literal.push_str("\\\""),
+                b'\\' => literal.push_str("\\\\"),
+                b' '..=b'~' => literal.push(byte as char),
+                _ => literal.push_str(&format!("\\{:03o}", byte)),
+            }
+        }
+        literal.push('"');
+
+        literal
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        let line = expression.span.line;
+        match &expression.kind {
+            ExpressionKind::Integer(value) => {
+                if i32::try_from(*value).is_ok() {
+                    self.emit(&format!("movq ${}, %rax", value));
+                } else {
+                    self.emit(&format!("movabsq ${}, %rax", value));
+                }
+            }
+            ExpressionKind::Bool(value) => {
+                self.emit(&format!("movq ${}, %rax", *value as i64));
+            }
+            ExpressionKind::String(value) => {
+                self.strings.push(Self::string_literal(value));
+                let label = format!("string_{}__rt", self.strings.len() - 1);
+                self.emit(&format!("leaq {}(%rip), %rax", label));
+            }
+            ExpressionKind::Variable(name) => {
+                let (value, _) = self.variable(name);
+                self.emit(&format!("movq {}, %rax", value));
+            }
+            ExpressionKind::Previous(name) => {
+                let (_, previous) = self.variable(name);
+                self.emit(&format!("movq {}, %rax", previous));
+            }
+            ExpressionKind::ParenthesisExpression(inner) => self.expression(inner),
+            ExpressionKind::UnaryOperation(UnaryOperator::Not, operand) => {
+                self.expression(operand);
+                self.emit("xorq $1, %rax");
+            }
+            // The left side is the result when the right side is skipped
+            ExpressionKind::BinaryOperation(
+                left,
+                operator @ (BinaryOperator::And | BinaryOperator::Or),
+                right,
+            ) => {
+                let end_label = self.new_label();
+                self.expression(left);
+                self.emit("testq %rax, %rax");
+                match operator {
+                    BinaryOperator::And => self.emit(&format!("jz {}", end_label)),
+                    _ => self.emit(&format!("jnz {}", end_label)),
+                }
+                self.expression(right);
+                self.place_label(&end_label);
+            }
+            ExpressionKind::BinaryOperation(left, operator, right) => {
+                self.expression(left);
+                self.emit("pushq %rax");
+                self.expression(right);
+                self.emit("movq %rax, %rcx");
+                self.emit("popq %rax");
+                self.binary_operation(operator, left.ty());
+            }
+            ExpressionKind::Call(name, arguments) => {
+                for argument in arguments.iter() {
+                    self.expression(argument);
+                    self.emit("pushq %rax");
+                }
+                self.emit(&format!("call {}__fn", name));
+                if !arguments.is_empty() {
+                    self.emit(&format!("addq ${}, %rsp", 8 * arguments.len()));
+                }
+            }
+            ExpressionKind::ArrayLiteral(elements) => {
+                for element in elements.iter() {
+                    self.expression(element);
+                    self.emit("pushq %rax");
+                }
+                self.emit(&format!("movq ${}, %rdi", elements.len()));
+                self.call_runtime("array_new__rt");
+                for i in (0..elements.len()).rev() {
+                    self.emit("popq %rcx");
+                    self.emit(&format!("movq %rcx, {}(%rax)", 8 * (i + 1)));
+                }
+            }
+            ExpressionKind::Index(array, index) => {
+                self.expression(array);
+                self.element_address(index, line);
+                self.emit("movq (%rax), %rax");
+            }
+            ExpressionKind::Length(array) => {
+                self.expression(array);
+                self.emit("movq (%rax), %rax");
+            }
+            ExpressionKind::Input => self.call_runtime("read_line__rt"),
+            ExpressionKind::ReadInt => {
+                self.emit(&format!("movq ${}, %rdi", line));
+                self.call_runtime("read_int__rt");
+            }
+        }
+    }
+
+    // Applies the operator to %rax and %rcx, leaving the result in %rax
+    fn binary_operation(&mut self, operator: &BinaryOperator, operand_type: &Type) {
+        let condition = match operator {
+            BinaryOperator::Add => return self.emit("addq %rcx, %rax"),
+            BinaryOperator::Subtract => return self.emit("subq %rcx, %rax"),
+            BinaryOperator::Multiply => return self.emit("imulq %rcx, %rax"),
+            BinaryOperator::Divide => {
+                self.emit("cqto");
+                return self.emit("idivq %rcx");
+            }
+            BinaryOperator::Equal | BinaryOperator::NotEqual if *operand_type == Type::String => {
+                self.emit("movq %rax, %rdi");
+                self.emit("movq %rcx, %rsi");
+                self.call_runtime("string_equal__rt");
+                if *operator == BinaryOperator::NotEqual {
+                    self.emit("xorq $1, %rax");
+                }
+                return;
+            }
+            BinaryOperator::Equal => "e",
+            BinaryOperator::NotEqual => "ne",
+            BinaryOperator::Less => "l",
+            BinaryOperator::LessEqual => "le",
+            BinaryOperator::Greater => "g",
+            BinaryOperator::GreaterEqual => "ge",
+            BinaryOperator::And | BinaryOperator::Or => {
+                unreachable!("&& and || are generated as jumps")
+            }
+        };
+
+        self.emit("cmpq %rcx, %rax");
+        self.emit(&format!("set{} %al", condition));
+        self.emit("movzbq %al, %rax");
+    }
+}
@@ -1,39 +1,161 @@
 use core::fmt;
 
+use crate::diagnostics::Diagnostic;
+use crate::lexer::Span;
 use crate::lexer::Token;
 use crate::lexer::TokenType;
 
-#[derive(Debug)]
-pub enum AstError {
+#[derive(Debug, Clone)]
+pub enum AstErrorKind {
     UnexpectedStatement(TokenType),
     BadAssignment(String),
     BadLoop,
+    BadWhileLoop,
+    BadForLoop,
     BadVariableDeclaration,
     ExpectedBang,
     BadIfStatement,
     UnexpectedToken(TokenType),
     ExpectedClosingParenthesis,
     ExpectedExpression,
+    BadPrevious,
+    BadLabel(String),
+    BadFunction,
+    ExpectedType,
+    ExpectedClosingBracket,
+    // Name of the builtin
+    BadBuiltin(String),
 }
 
-impl fmt::Display for AstError {
+#[derive(Debug)]
+pub struct AstError {
+    pub kind: AstErrorKind,
+    pub span: Span,
+}
+
+impl fmt::Display for AstErrorKind {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            AstError::UnexpectedStatement(token_type) => write!(
+            AstErrorKind::UnexpectedStatement(token_type) => write!(
                 f,
                 "Unexpected statement, began with token type {:?}",
                 token_type
             ),
-            AstError::BadAssignment(identifier) => write!(f, "Bad assignment for {}", identifier),
-            AstError::BadLoop => write!(f, "Bad loop"),
-            AstError::BadVariableDeclaration => write!(f, "Bad variable declaration"),
-            AstError::ExpectedBang => write!(f, "Expected bang"),
-            AstError::BadIfStatement => write!(f, "Bad if statement"),
-            AstError::UnexpectedToken(token_type) => {
+            AstErrorKind::BadAssignment(identifier) => {
+                write!(f, "Bad assignment for {}", identifier)
+            }
+            AstErrorKind::BadLoop => write!(f, "Bad loop"),
+            AstErrorKind::BadWhileLoop => write!(f, "Bad while loop"),
+            AstErrorKind::BadForLoop => write!(f, "Bad for loop"),
+            AstErrorKind::BadVariableDeclaration => write!(f, "Bad variable declaration"),
+            AstErrorKind::ExpectedBang => write!(f, "Expected bang"),
+            AstErrorKind::BadIfStatement => write!(f, "Bad if statement"),
+            AstErrorKind::UnexpectedToken(token_type) => {
                 write!(f, "Unexpected token of type {:?}", token_type)
             }
-            AstError::ExpectedClosingParenthesis => write!(f, "Expected closing parenthesis"),
-            AstError::ExpectedExpression => write!(f, "Expected an expression"),
+            AstErrorKind::ExpectedClosingParenthesis => write!(f, "Expected closing parenthesis"),
+            AstErrorKind::ExpectedExpression => write!(f, "Expected an expression"),
+            AstErrorKind::BadPrevious => write!(f, "Expected a variable after prev"),
+            AstErrorKind::BadLabel(label) => write!(f, "Label {} is not followed by a loop", label),
+            AstErrorKind::BadFunction => write!(f, "Bad function declaration"),
+            AstErrorKind::ExpectedType => write!(f, "Expected a type"),
+            AstErrorKind::ExpectedClosingBracket => write!(f, "Expected closing bracket"),
+            AstErrorKind::BadBuiltin(name) => write!(f, "Expected parenthesis after {}", name),
+        }
+    }
+}
+
+impl fmt::Display for AstError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}", self.kind, self.span)
+    }
+}
+
+impl AstError {
+    pub fn diagnostic(&self) -> Diagnostic {
+        match &self.kind {
+            AstErrorKind::UnexpectedStatement(token_type) => Diagnostic::error(
+                "E0201",
+                format!("expected a statement, found {}", token_type),
+                self.span,
+            )
+            .with_label("not the start of a statement")
+            .with_help(
+                "statements start with `var`, `print`, `if`, a loop, `break`, `continue`, `fn`, `return` or a name",
+            ),
+            AstErrorKind::BadAssignment(identifier) => Diagnostic::error(
+                "E0202",
+                format!("expected `=` after `{}`", identifier),
+                self.span,
+            )
+            .with_label("expected `=` here")
+            .with_help(format!("assign a new value with `{} = value!`", identifier)),
+            AstErrorKind::BadLoop => Diagnostic::error("E0203", "malformed loop", self.span)
+                .with_label("unexpected token in loop")
+                .with_help("loops are written as `loop { ... }`"),
+            AstErrorKind::BadWhileLoop => {
+                Diagnostic::error("E0211", "malformed while loop", self.span)
+                    .with_label("unexpected token in while loop")
+                    .with_help("while loops are written as `while (condition) { ... }`")
+            }
+            AstErrorKind::BadForLoop => Diagnostic::error("E0212", "malformed for loop", self.span)
+                .with_label("unexpected token in for loop")
+                .with_help("for loops are written as `for i in start..end { ... }`"),
+            AstErrorKind::BadVariableDeclaration => {
+                Diagnostic::error("E0204", "malformed variable declaration", self.span)
+                    .with_label("unexpected token in declaration")
+                    .with_help("variables are declared as `var name = value!`")
+            }
+            AstErrorKind::ExpectedBang => Diagnostic::error("E0205", "expected `!`", self.span)
+                .with_label("expected `!` here")
+                .with_help("every statement in trunk++ ends with a `!`"),
+            AstErrorKind::BadIfStatement => {
+                Diagnostic::error("E0206", "malformed if statement", self.span)
+                    .with_label("unexpected token in if statement")
+                    .with_help("if statements are written as `if (condition) { ... } else { ... }`")
+            }
+            AstErrorKind::UnexpectedToken(token_type) => Diagnostic::error(
+                "E0207",
+                format!("unexpected {} in expression", token_type),
+                self.span,
+            )
+            .with_label("expected a value here"),
+            AstErrorKind::ExpectedClosingParenthesis => {
+                Diagnostic::error("E0208", "expected closing parenthesis", self.span)
+                    .with_label("expected `)` here")
+            }
+            AstErrorKind::ExpectedExpression => {
+                Diagnostic::error("E0209", "expected an expression", self.span)
+                    .with_label("expected a value here")
+            }
+            AstErrorKind::BadPrevious => {
+                Diagnostic::error("E0210", "expected a variable after `prev`", self.span)
+                    .with_label("expected a variable name here")
+                    .with_help("`prev x` is the value `x` had before its last assignment")
+            }
+            AstErrorKind::BadLabel(label) => Diagnostic::error(
+                "E0213",
+                format!("label `{}` is not followed by a loop", label),
+                self.span,
+            )
+            .with_label("expected `loop`, `while` or `for` here")
+            .with_note("only loops can be labeled"),
+            AstErrorKind::BadFunction => {
+                Diagnostic::error("E0214", "malformed function declaration", self.span)
+                    .with_label("unexpected token in function declaration")
+                    .with_help("functions are declared as `fn name(a, b: string) -> int { ... }`")
+            }
+            AstErrorKind::ExpectedType => Diagnostic::error("E0215", "expected a type", self.span)
+                .with_label("expected `int`, `bool`, `string` or `[type]` here"),
+            AstErrorKind::ExpectedClosingBracket => {
+                Diagnostic::error("E0216", "expected closing bracket", self.span)
+                    .with_label("expected `]` here")
+            }
+            AstErrorKind::BadBuiltin(name) => {
+                Diagnostic::error("E0217", format!("malformed `{}`", name), self.span)
+                    .with_label("expected `(` here")
+                    .with_help("builtins are called like functions, e.g. `len(array)` or `input()`")
+            }
         }
     }
 }
@@ -44,47 +166,107 @@ pub struct Ast {
 }
 
 impl Ast {
-    pub fn print(&self) {
+    pub fn pretty(&self) -> String {
+        let mut output = String::new();
         for statement in self.statements.iter() {
-            Self::print_statement(statement, 0);
+            Self::print_statement(&mut output, statement, 0);
         }
+        output
     }
 
     // kinda pretty prints the ast (enough to be readable at least)
-    fn print_statement(statement: &Statement, indentation: usize) {
-        match statement {
-            Statement::LoopStatement(statements) => {
-                Self::print_indented("Loop:".to_string(), indentation);
+    fn print_statement(output: &mut String, statement: &Statement, indentation: usize) {
+        match &statement.kind {
+            StatementKind::LoopStatement(statements) => {
+                Self::print_indented(output, "Loop:".to_string(), indentation);
+                for statement in statements {
+                    Self::print_statement(output, statement, indentation + 1);
+                }
+            }
+            StatementKind::LabeledStatement(label, statement) => {
+                Self::print_indented(output, format!("{}:", label), indentation);
+                Self::print_statement(output, statement, indentation);
+            }
+            StatementKind::WhileStatement(condition, statements) => {
+                Self::print_indented(output, "While:".to_string(), indentation);
+                Self::print_indented(output, format!("{:?}", condition), indentation + 1);
+
+                Self::print_indented(output, "Do:".to_string(), indentation);
+                for statement in statements {
+                    Self::print_statement(output, statement, indentation + 1);
+                }
+            }
+            StatementKind::ForStatement(variable, start, end, statements) => {
+                Self::print_indented(output, format!("For {} in:", variable), indentation);
+                Self::print_indented(output, format!("{:?}", start), indentation + 1);
+                Self::print_indented(output, format!("{:?}", end), indentation + 1);
+
+                Self::print_indented(output, "Do:".to_string(), indentation);
+                for statement in statements {
+                    Self::print_statement(output, statement, indentation + 1);
+                }
+            }
+            StatementKind::FunctionDeclaration(name, parameters, return_type, statements) => {
+                let parameters: Vec<String> = parameters
+                    .iter()
+                    .map(|(parameter, ty)| format!("{}: {}", parameter, ty))
+                    .collect();
+                let return_type = match return_type {
+                    Some(ty) => format!(" -> {}", ty),
+                    None => String::new(),
+                };
+                Self::print_indented(
+                    output,
+                    format!(
+                        "Function {}({}){}:",
+                        name,
+                        parameters.join(", "),
+                        return_type
+                    ),
+                    indentation,
+                );
                 for statement in statements {
-                    Self::print_statement(statement, indentation + 1);
+                    Self::print_statement(output, statement, indentation + 1);
                 }
             }
-            Statement::IfStatement(expression, statements) => {
-                Self::print_indented("If:".to_string(), indentation);
-                Self::print_indented(format!("{:?}", expression), indentation + 1);
+            StatementKind::IfStatement(expression, statements, else_statements) => {
+                Self::print_indented(output, "If:".to_string(), indentation);
+                Self::print_indented(output, format!("{:?}", expression), indentation + 1);
 
-                Self::print_indented("Then:".to_string(), indentation);
+                Self::print_indented(output, "Then:".to_string(), indentation);
                 for statement in statements {
-                    Self::print_statement(statement, indentation + 1);
+                    Self::print_statement(output, statement, indentation + 1);
+                }
+
+                if let Some(else_statements) = else_statements {
+                    Self::print_indented(output, "Else:".to_string(), indentation);
+                    for statement in else_statements {
+                        Self::print_statement(output, statement, indentation + 1);
+                    }
                 }
             }
-            _ => Self::print_indented(format!("{:?}", statement), indentation),
+            kind => Self::print_indented(output, format!("{:?}", kind), indentation),
         };
     }
 
-    fn print_indented(to_print: String, indentation: usize) {
-        println!("{}{}", " ".repeat(indentation * 4), to_print);
+    fn print_indented(output: &mut String, to_print: String, indentation: usize) {
+        *output += &format!("{}{}\n", " ".repeat(indentation * 4), to_print);
     }
 }
 
 pub struct AstParser {
     tokens: Vec<Token>,
     index: usize,
+    errors: Vec<AstError>,
 }
 
 impl AstParser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        AstParser { tokens, index: 0 }
+        AstParser {
+            tokens,
+            index: 0,
+            errors: Vec::new(),
+        }
     }
 
     fn peek(&self) -> Option<&Token> {
@@ -102,190 +284,488 @@ impl AstParser {
         self.index += 1;
     }
 
-    pub fn parse(&mut self) -> Result<Ast, AstError> {
+    // Span of the next token, or an empty span right after the last token
+    // when we ran out of tokens
+    fn current_span(&self) -> Span {
+        match self.peek() {
+            Some(token) => token.span,
+            None => match self.tokens.last() {
+                Some(token) => Self::span_after(token.span),
+                None => Span::default(),
+            },
+        }
+    }
+
+    // Empty span right behind the given one, for pointing at something missing
+    fn span_after(span: Span) -> Span {
+        Span {
+            start: span.end,
+            end: span.end,
+            line: span.line,
+            column: span.end_column,
+            end_column: span.end_column,
+        }
+    }
+
+    // Span of the last consumed token
+    fn previous_span(&self) -> Span {
+        match self.index.checked_sub(1).and_then(|i| self.tokens.get(i)) {
+            Some(token) => token.span,
+            None => self.current_span(),
+        }
+    }
+
+    fn error(&self, kind: AstErrorKind) -> AstError {
+        AstError {
+            kind,
+            span: self.current_span(),
+        }
+    }
+
+    // Parses the whole program. Statements with syntax errors are left out of
+    // the returned ast, so it is only complete if no errors were returned
+    pub fn parse(&mut self) -> (Ast, Vec<AstError>) {
         let mut ast = Ast {
             statements: Vec::new(),
         };
 
         while self.index < self.tokens.len() {
-            let statement = self.parse_statement()?;
-            ast.statements.push(statement);
+            if let Some(statement) = self.parse_statement_or_recover() {
+                ast.statements.push(statement);
+            }
+        }
+
+        (ast, std::mem::take(&mut self.errors))
+    }
+
+    // Parses statements up until the closing bracket of the block
+    fn parse_block(&mut self) -> Vec<Statement> {
+        let mut statements: Vec<Statement> = Vec::new();
+        while let Some(token) = self.peek() {
+            if token.token_type == TokenType::CloseBrace {
+                break;
+            }
+            if let Some(statement) = self.parse_statement_or_recover() {
+                statements.push(statement);
+            }
+        }
+
+        statements
+    }
+
+    fn parse_statement_or_recover(&mut self) -> Option<Statement> {
+        match self.parse_statement() {
+            Ok(statement) => Some(statement),
+            Err(err) => {
+                self.errors.push(err);
+                self.synchronize();
+                None
+            }
         }
+    }
+
+    // Panic mode recovery: skips tokens until the end of the broken statement,
+    // which is the next `!`, the `}` closing the enclosing block or a keyword
+    // that starts a new statement. Blocks opened while skipping are skipped
+    // as a whole
+    fn synchronize(&mut self) {
+        let mut depth = 0;
 
-        Ok(ast)
+        while let Some(token) = self.peek() {
+            match token.token_type {
+                TokenType::Bang if depth == 0 => {
+                    self.consume();
+                    return;
+                }
+                TokenType::Var
+                | TokenType::Print
+                | TokenType::If
+                | TokenType::Loop
+                | TokenType::While
+                | TokenType::For
+                | TokenType::Fn
+                | TokenType::Return
+                    if depth == 0 =>
+                {
+                    return
+                }
+                TokenType::OpenBrace => depth += 1,
+                TokenType::CloseBrace if depth == 0 => return,
+                TokenType::CloseBrace => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.consume();
+                        return;
+                    }
+                }
+                _ => {}
+            }
+            self.consume();
+        }
     }
 
     fn parse_statement(&mut self) -> Result<Statement, AstError> {
+        let start = self.current_span();
         let next_token_type = self.tokens[self.index].token_type.clone();
 
         self.consume();
 
-        match next_token_type {
-            TokenType::Break => {
-                self.consume();
-                Ok(Statement::BreakStatement)
-            }
-            TokenType::Identifier(identifier) => self.parse_assignment(identifier),
-            TokenType::Loop => self.parse_loop(),
-            TokenType::Var => self.parse_variable_declaration(),
-            TokenType::If => self.parse_if_statement(),
-            TokenType::Print => self.parse_print_statement(),
-            _ => Err(AstError::UnexpectedStatement(next_token_type)),
-        }
+        let kind = match next_token_type {
+            TokenType::Break => StatementKind::BreakStatement(self.parse_loop_jump_label()?),
+            TokenType::Continue => StatementKind::ContinueStatement(self.parse_loop_jump_label()?),
+            TokenType::Identifier(identifier)
+                if self
+                    .peek()
+                    .is_some_and(|token| token.token_type == TokenType::Colon) =>
+            {
+                self.parse_labeled_loop(identifier)?
+            }
+            // Only calls can be used as statements, for their side effects
+            TokenType::Identifier(identifier)
+                if self
+                    .peek()
+                    .is_some_and(|token| token.token_type == TokenType::OpenParen) =>
+            {
+                let arguments = self.parse_arguments()?;
+                let call = Expression::new(
+                    ExpressionKind::Call(identifier, arguments),
+                    start.to(self.previous_span()),
+                );
+                self.expect_bang()?;
+                StatementKind::ExpressionStatement(call)
+            }
+            TokenType::Identifier(identifier)
+                if self
+                    .peek()
+                    .is_some_and(|token| token.token_type == TokenType::OpenBracket) =>
+            {
+                self.parse_index_assignment(identifier)?
+            }
+            TokenType::Identifier(identifier) => self.parse_assignment(identifier)?,
+            TokenType::Loop => self.parse_loop()?,
+            TokenType::While => self.parse_while_loop()?,
+            TokenType::For => self.parse_for_loop()?,
+            TokenType::Var => self.parse_variable_declaration()?,
+            TokenType::If => self.parse_if_statement()?,
+            TokenType::Print => self.parse_print_statement()?,
+            TokenType::Fn => self.parse_function()?,
+            TokenType::Return => self.parse_return_statement()?,
+            _ => {
+                return Err(AstError {
+                    kind: AstErrorKind::UnexpectedStatement(next_token_type),
+                    span: start,
+                })
+            }
+        };
+
+        Ok(Statement {
+            kind,
+            span: start.to(self.previous_span()),
+        })
     }
 
-    fn parse_print_statement(&mut self) -> Result<Statement, AstError> {
-        let expression = self.parse_expression()?;
+    // Parses the optional label and the bang after `break` or `continue`
+    fn parse_loop_jump_label(&mut self) -> Result<Option<String>, AstError> {
+        let label = match self.peek().map(|token| token.token_type.clone()) {
+            Some(TokenType::Identifier(label)) => {
+                self.consume();
+                Some(label)
+            }
+            _ => None,
+        };
 
         self.expect_bang()?;
 
-        Ok(Statement::PrintStatement(expression))
+        Ok(label)
     }
 
-    fn parse_loop(&mut self) -> Result<Statement, AstError> {
-        // Opening bracket
-        let next_token = match self.peek().cloned() {
-            Some(token) => token,
-            None => return Err(AstError::BadLoop),
+    // Parses `label: loop { ... }`, the label has already been consumed
+    fn parse_labeled_loop(&mut self, label: String) -> Result<StatementKind, AstError> {
+        self.consume();
+
+        let start = self.current_span();
+        let kind = match self.peek().map(|token| token.token_type.clone()) {
+            Some(TokenType::Loop) => {
+                self.consume();
+                self.parse_loop()?
+            }
+            Some(TokenType::While) => {
+                self.consume();
+                self.parse_while_loop()?
+            }
+            Some(TokenType::For) => {
+                self.consume();
+                self.parse_for_loop()?
+            }
+            _ => return Err(self.error(AstErrorKind::BadLabel(label))),
         };
 
-        match &next_token.token_type {
-            TokenType::OpenBrace => {}
-            _ => return Err(AstError::BadLoop),
+        Ok(StatementKind::LabeledStatement(
+            label,
+            Box::new(Statement {
+                kind,
+                span: start.to(self.previous_span()),
+            }),
+        ))
+    }
+
+    // Parses `fn name(a, b: type) -> type { ... }`. Parameters without a type
+    // are ints. Without `-> type` the function returns an int if it returns a
+    // value anywhere, and nothing otherwise
+    fn parse_function(&mut self) -> Result<StatementKind, AstError> {
+        let name = match self.peek().map(|token| token.token_type.clone()) {
+            Some(TokenType::Identifier(name)) => name,
+            _ => return Err(self.error(AstErrorKind::BadFunction)),
         };
         self.consume();
 
-        // Parse the block of statements
-        let mut statements: Vec<Statement> = Vec::new();
-        while let Some(token) = self.peek() {
-            if token.token_type == TokenType::CloseBrace {
-                break;
+        self.expect(TokenType::OpenParen, AstErrorKind::BadFunction)?;
+        let mut parameters = Vec::new();
+        while let Some(TokenType::Identifier(parameter)) =
+            self.peek().map(|token| token.token_type.clone())
+        {
+            self.consume();
+
+            let ty = match self.peek().map(|token| &token.token_type) {
+                Some(TokenType::Colon) => {
+                    self.consume();
+                    self.parse_type()?
+                }
+                _ => Type::Int,
+            };
+            parameters.push((parameter, ty));
+
+            match self.peek().map(|token| &token.token_type) {
+                Some(TokenType::Comma) => self.consume(),
+                _ => break,
             }
-            statements.push(self.parse_statement()?);
         }
+        self.expect(TokenType::CloseParen, AstErrorKind::BadFunction)?;
 
-        // Closing bracket
-        let next_token = match self.peek().cloned() {
-            Some(token) => token,
-            None => return Err(AstError::BadLoop),
+        let return_type = match self.peek().map(|token| &token.token_type) {
+            Some(TokenType::Arrow) => {
+                self.consume();
+                Some(self.parse_type()?)
+            }
+            _ => None,
         };
 
-        match &next_token.token_type {
-            TokenType::CloseBrace => {}
-            _ => return Err(AstError::BadLoop),
+        let statements = self.parse_braced_block(AstErrorKind::BadFunction)?;
+        let return_type =
+            return_type.or_else(|| Self::returns_value(&statements).then_some(Type::Int));
+
+        Ok(StatementKind::FunctionDeclaration(
+            name,
+            parameters,
+            return_type,
+            statements,
+        ))
+    }
+
+    // Whether a function body has a `return` with a value, functions declared
+    // inside it are not looked at
+    pub fn returns_value(statements: &[Statement]) -> bool {
+        statements.iter().any(|statement| match &statement.kind {
+            StatementKind::ReturnStatement(value) => value.is_some(),
+            StatementKind::IfStatement(_, statements, else_statements) => {
+                Self::returns_value(statements)
+                    || else_statements.as_deref().is_some_and(Self::returns_value)
+            }
+            StatementKind::LoopStatement(statements)
+            | StatementKind::WhileStatement(_, statements)
+            | StatementKind::ForStatement(_, _, _, statements) => Self::returns_value(statements),
+            StatementKind::LabeledStatement(_, statement) => {
+                Self::returns_value(std::slice::from_ref(statement))
+            }
+            _ => false,
+        })
+    }
+
+    fn parse_type(&mut self) -> Result<Type, AstError> {
+        let ty = match self.peek().map(|token| &token.token_type) {
+            Some(TokenType::OpenBracket) => {
+                self.consume();
+                let element = self.parse_type()?;
+                self.expect(TokenType::CloseBracket, AstErrorKind::ExpectedType)?;
+                return Ok(Type::Array(Box::new(element)));
+            }
+            Some(TokenType::Identifier(name)) if name == "int" => Type::Int,
+            Some(TokenType::Identifier(name)) if name == "bool" => Type::Bool,
+            Some(TokenType::Identifier(name)) if name == "string" => Type::String,
+            _ => return Err(self.error(AstErrorKind::ExpectedType)),
         };
         self.consume();
 
-        Ok(Statement::LoopStatement(statements))
+        Ok(ty)
     }
 
-    fn parse_assignment(&mut self, identifier: String) -> Result<Statement, AstError> {
-        // Identifier
-        let next_token = match self.peek().cloned() {
-            Some(token) => token,
-            None => return Err(AstError::BadAssignment(identifier)),
+    // A `!` right after `return` ends the statement, so `return !x!` has to
+    // be written as `return (!x)!`
+    fn parse_return_statement(&mut self) -> Result<StatementKind, AstError> {
+        let value = match self.peek().map(|token| &token.token_type) {
+            Some(TokenType::Bang) | None => None,
+            _ => Some(self.parse_expression()?),
         };
 
-        match &next_token.token_type {
-            TokenType::Equal => {}
-            _ => return Err(AstError::BadAssignment(identifier)),
-        };
-        self.consume();
+        self.expect_bang()?;
+
+        Ok(StatementKind::ReturnStatement(value))
+    }
 
+    fn parse_print_statement(&mut self) -> Result<StatementKind, AstError> {
         let expression = self.parse_expression()?;
 
         self.expect_bang()?;
 
-        Ok(Statement::Assignment(identifier, expression))
+        Ok(StatementKind::PrintStatement(expression))
     }
 
-    fn parse_if_statement(&mut self) -> Result<Statement, AstError> {
-        // Opening parenthesis
-        let next_token = match self.peek().cloned() {
-            Some(token) => token,
-            None => return Err(AstError::BadIfStatement),
-        };
-
-        match &next_token.token_type {
-            TokenType::OpenParen => {}
-            _ => return Err(AstError::BadIfStatement),
-        };
+    fn parse_loop(&mut self) -> Result<StatementKind, AstError> {
+        let statements = self.parse_braced_block(AstErrorKind::BadLoop)?;
 
-        self.consume();
+        Ok(StatementKind::LoopStatement(statements))
+    }
 
-        // Parse expression
+    fn parse_while_loop(&mut self) -> Result<StatementKind, AstError> {
+        self.expect(TokenType::OpenParen, AstErrorKind::BadWhileLoop)?;
         let condition = self.parse_expression()?;
+        self.expect(TokenType::CloseParen, AstErrorKind::BadWhileLoop)?;
 
-        // Closing parenthesis
-        let next_token = match self.peek().cloned() {
-            Some(token) => token,
-            None => return Err(AstError::BadIfStatement),
-        };
+        let statements = self.parse_braced_block(AstErrorKind::BadWhileLoop)?;
 
-        match &next_token.token_type {
-            TokenType::CloseParen => {}
-            _ => return Err(AstError::BadIfStatement),
+        Ok(StatementKind::WhileStatement(condition, statements))
+    }
+
+    fn parse_for_loop(&mut self) -> Result<StatementKind, AstError> {
+        let variable = match self.peek().map(|token| token.token_type.clone()) {
+            Some(TokenType::Identifier(name)) => name,
+            _ => return Err(self.error(AstErrorKind::BadForLoop)),
         };
         self.consume();
 
-        // Opening bracket
+        self.expect(TokenType::In, AstErrorKind::BadForLoop)?;
+        let start = self.parse_expression()?;
+        self.expect(TokenType::DotDot, AstErrorKind::BadForLoop)?;
+        let end = self.parse_expression()?;
+
+        let statements = self.parse_braced_block(AstErrorKind::BadForLoop)?;
+
+        Ok(StatementKind::ForStatement(
+            variable, start, end, statements,
+        ))
+    }
+
+    fn parse_assignment(&mut self, identifier: String) -> Result<StatementKind, AstError> {
+        // Identifier
         let next_token = match self.peek().cloned() {
             Some(token) => token,
-            None => return Err(AstError::BadIfStatement),
+            None => return Err(self.error(AstErrorKind::BadAssignment(identifier))),
         };
 
         match &next_token.token_type {
-            TokenType::OpenBrace => {}
-            _ => return Err(AstError::BadIfStatement),
+            TokenType::Equal => {}
+            _ => return Err(self.error(AstErrorKind::BadAssignment(identifier))),
         };
         self.consume();
 
-        // Parse the block of statements
-        let mut statements: Vec<Statement> = Vec::new();
-        while let Some(token) = self.peek() {
-            if token.token_type == TokenType::CloseBrace {
-                break;
-            }
-            statements.push(self.parse_statement()?);
+        let expression = self.parse_expression()?;
+
+        self.expect_bang()?;
+
+        Ok(StatementKind::Assignment(identifier, expression))
+    }
+
+    // Parses `name[index]... = value!`, the name has already been consumed
+    fn parse_index_assignment(&mut self, identifier: String) -> Result<StatementKind, AstError> {
+        let mut indices = Vec::new();
+        while self
+            .peek()
+            .is_some_and(|token| token.token_type == TokenType::OpenBracket)
+        {
+            indices.push(self.parse_index()?);
         }
 
-        // Closing bracket
-        let next_token = match self.peek().cloned() {
-            Some(token) => token,
-            None => return Err(AstError::BadIfStatement),
-        };
+        self.expect(
+            TokenType::Equal,
+            AstErrorKind::BadAssignment(identifier.clone()),
+        )?;
+        let expression = self.parse_expression()?;
 
-        match &next_token.token_type {
-            TokenType::CloseBrace => {}
-            _ => return Err(AstError::BadIfStatement),
-        };
+        self.expect_bang()?;
+
+        Ok(StatementKind::IndexAssignment(
+            identifier, indices, expression,
+        ))
+    }
+
+    // Parses `[index]`
+    fn parse_index(&mut self) -> Result<Expression, AstError> {
         self.consume();
+        let index = self.parse_expression()?;
+        self.expect(
+            TokenType::CloseBracket,
+            AstErrorKind::ExpectedClosingBracket,
+        )?;
 
-        Ok(Statement::IfStatement(condition, statements))
+        Ok(index)
     }
 
-    fn parse_variable_declaration(&mut self) -> Result<Statement, AstError> {
+    fn parse_if_statement(&mut self) -> Result<StatementKind, AstError> {
+        self.expect(TokenType::OpenParen, AstErrorKind::BadIfStatement)?;
+        let condition = self.parse_expression()?;
+        self.expect(TokenType::CloseParen, AstErrorKind::BadIfStatement)?;
+
+        let statements = self.parse_braced_block(AstErrorKind::BadIfStatement)?;
+
+        // An `else if` is stored as an else branch holding just that if statement
+        let else_statements = match self.peek().map(|token| token.token_type.clone()) {
+            Some(TokenType::Else) => {
+                self.consume();
+                match self.peek().map(|token| token.token_type.clone()) {
+                    Some(TokenType::If) => {
+                        let start = self.current_span();
+                        self.consume();
+                        let kind = self.parse_if_statement()?;
+                        Some(vec![Statement {
+                            kind,
+                            span: start.to(self.previous_span()),
+                        }])
+                    }
+                    _ => Some(self.parse_braced_block(AstErrorKind::BadIfStatement)?),
+                }
+            }
+            _ => None,
+        };
+
+        Ok(StatementKind::IfStatement(
+            condition,
+            statements,
+            else_statements,
+        ))
+    }
+
+    fn parse_variable_declaration(&mut self) -> Result<StatementKind, AstError> {
         let next_token = match self.peek().cloned() {
             Some(token) => token,
-            None => return Err(AstError::BadVariableDeclaration),
+            None => return Err(self.error(AstErrorKind::BadVariableDeclaration)),
         };
 
-        self.consume();
-
         let identifier = match &next_token.token_type {
             TokenType::Identifier(name) => name.to_string(),
-            _ => return Err(AstError::BadVariableDeclaration),
+            _ => return Err(self.error(AstErrorKind::BadVariableDeclaration)),
         };
 
+        self.consume();
+
         if self
             .peek()
-            .map_or(true, |token| token.token_type != TokenType::Equal)
+            .is_none_or(|token| token.token_type != TokenType::Equal)
         {
-            return Err(AstError::BadVariableDeclaration);
+            return Err(self.error(AstErrorKind::BadVariableDeclaration));
         }
         self.consume();
 
-        let expression = Statement::VariableDeclaration(identifier, self.parse_expression()?);
+        let expression = StatementKind::VariableDeclaration(identifier, self.parse_expression()?);
 
         self.expect_bang()?;
 
@@ -293,124 +773,303 @@ impl AstParser {
     }
 
     fn parse_expression(&mut self) -> Result<Expression, AstError> {
-        Ok(self.parse_add_sub_expression()?)
+        self.parse_binary_expression(1)
     }
 
-    fn parse_add_sub_expression(&mut self) -> Result<Expression, AstError> {
-        // Multiplication and division has higher priority
-        let mut left = self.parse_mult_div_expression()?;
+    // Binary operators and how tightly they bind. Operators with a higher
+    // precedence are grouped first, and all of them are left associative, so
+    // `a - b - c` is `(a - b) - c`
+    //
+    // | precedence | operators            |
+    // |------------|----------------------|
+    // | 1          | `||`                 |
+    // | 2          | `&&`                 |
+    // | 3          | `==` `;=`            |
+    // | 4          | `<` `<=` `>` `>=`    |
+    // | 5          | `+` `-`              |
+    // | 6          | `*` `/`              |
+    //
+    // The prefix operators `!` and `not` bind tighter than all of them, so
+    // `!a == b` is `(!a) == b`
+    fn binary_operator(token_type: &TokenType) -> Option<(BinaryOperator, u8)> {
+        match token_type {
+            TokenType::PipePipe => Some((BinaryOperator::Or, 1)),
+            TokenType::AmpersandAmpersand => Some((BinaryOperator::And, 2)),
+            TokenType::EqualEqual => Some((BinaryOperator::Equal, 3)),
+            TokenType::SemicolonEqual => Some((BinaryOperator::NotEqual, 3)),
+            TokenType::Less => Some((BinaryOperator::Less, 4)),
+            TokenType::LessEqual => Some((BinaryOperator::LessEqual, 4)),
+            TokenType::Greater => Some((BinaryOperator::Greater, 4)),
+            TokenType::GreaterEqual => Some((BinaryOperator::GreaterEqual, 4)),
+            TokenType::Plus => Some((BinaryOperator::Add, 5)),
+            TokenType::Minus => Some((BinaryOperator::Subtract, 5)),
+            TokenType::Star => Some((BinaryOperator::Multiply, 6)),
+            TokenType::Slash => Some((BinaryOperator::Divide, 6)),
+            _ => None,
+        }
+    }
 
-        while let Some(token) = self.peek().cloned() {
-            match token.token_type {
-                TokenType::Plus | TokenType::Minus => {
-                    self.consume();
-                    let right = self.parse_mult_div_expression()?;
-                    left = Expression::BinaryOperation(
-                        Box::new(left),
-                        match token.token_type {
-                            TokenType::Plus => BinaryOperator::Add,
-                            TokenType::Minus => BinaryOperator::Subtract,
-                            _ => unreachable!(),
-                        },
-                        Box::new(right),
-                    );
-                }
-                _ => break,
+    // Precedence climbing: parses an operand and then keeps folding in
+    // operators that bind at least as tight as min_precedence. The right hand
+    // side only takes operators that bind tighter, which makes every
+    // operator left associative
+    fn parse_binary_expression(&mut self, min_precedence: u8) -> Result<Expression, AstError> {
+        let mut left = self.parse_primary()?;
+
+        while let Some((operator, precedence)) = self
+            .peek()
+            .and_then(|token| Self::binary_operator(&token.token_type))
+        {
+            if precedence < min_precedence {
+                break;
             }
+            self.consume();
+
+            let right = self.parse_binary_expression(precedence + 1)?;
+            left = Self::binary_operation(left, operator, right);
         }
 
         Ok(left)
     }
 
-    fn parse_mult_div_expression(&mut self) -> Result<Expression, AstError> {
-        // Parse identifiers / integers / parenthesis first
-        let mut left = self.parse_comparision_expression()?;
+    fn binary_operation(
+        left: Expression,
+        operator: BinaryOperator,
+        right: Expression,
+    ) -> Expression {
+        let span = left.span.to(right.span);
+        Expression::new(
+            ExpressionKind::BinaryOperation(Box::new(left), operator, Box::new(right)),
+            span,
+        )
+    }
 
-        while let Some(token) = self.peek().cloned() {
-            match token.token_type {
-                TokenType::Star | TokenType::Slash => {
-                    self.consume();
-                    let right = self.parse_primary()?;
-                    left = Expression::BinaryOperation(
-                        Box::new(left),
-                        match token.token_type {
-                            TokenType::Star => BinaryOperator::Multiply,
-                            TokenType::Slash => BinaryOperator::Divide,
-                            _ => unreachable!(),
-                        },
-                        Box::new(right),
-                    );
+    fn parse_primary(&mut self) -> Result<Expression, AstError> {
+        let Some(token) = self.peek().cloned() else {
+            return Err(self.error(AstErrorKind::ExpectedExpression));
+        };
+
+        let kind = match &token.token_type {
+            TokenType::Integer(value) => {
+                self.consume();
+                ExpressionKind::Integer(*value)
+            }
+            TokenType::Bool(value) => {
+                self.consume();
+                ExpressionKind::Bool(*value)
+            }
+            TokenType::String(value) => {
+                self.consume();
+                ExpressionKind::String(value.to_string())
+            }
+            // A `!` where a value is expected is a logical not, anywhere else
+            // it ends the statement
+            TokenType::Bang | TokenType::Not => {
+                self.consume();
+
+                // Without an operand the `!` most likely was meant to end the
+                // statement, so that is where the error goes instead of at
+                // whatever comes next
+                if self
+                    .peek()
+                    .is_none_or(|next| Self::ends_operand(&next.token_type))
+                {
+                    return Err(AstError {
+                        kind: AstErrorKind::ExpectedExpression,
+                        span: token.span,
+                    });
                 }
-                _ => break,
+
+                let operand = self.parse_primary()?;
+                ExpressionKind::UnaryOperation(UnaryOperator::Not, Box::new(operand))
+            }
+            TokenType::Identifier(name)
+                if self
+                    .peek_fw(1)
+                    .is_some_and(|token| token.token_type == TokenType::OpenParen) =>
+            {
+                self.consume();
+                ExpressionKind::Call(name.to_string(), self.parse_arguments()?)
+            }
+            TokenType::Identifier(name) => {
+                self.consume();
+                ExpressionKind::Variable(name.to_string())
+            }
+            TokenType::Previous => {
+                self.consume();
+                match self.peek().map(|token| token.token_type.clone()) {
+                    Some(TokenType::Identifier(name)) => {
+                        self.consume();
+                        ExpressionKind::Previous(name)
+                    }
+                    _ => return Err(self.error(AstErrorKind::BadPrevious)),
+                }
+            }
+            TokenType::OpenBracket => {
+                self.consume();
+                let mut elements = Vec::new();
+                if self
+                    .peek()
+                    .is_some_and(|token| token.token_type != TokenType::CloseBracket)
+                {
+                    loop {
+                        elements.push(self.parse_expression()?);
+                        match self.peek().map(|token| &token.token_type) {
+                            Some(TokenType::Comma) => self.consume(),
+                            _ => break,
+                        }
+                    }
+                }
+                self.expect(
+                    TokenType::CloseBracket,
+                    AstErrorKind::ExpectedClosingBracket,
+                )?;
+                ExpressionKind::ArrayLiteral(elements)
+            }
+            TokenType::Len => {
+                self.consume();
+                self.expect(
+                    TokenType::OpenParen,
+                    AstErrorKind::BadBuiltin("len".to_string()),
+                )?;
+                let array = self.parse_expression()?;
+                self.expect(
+                    TokenType::CloseParen,
+                    AstErrorKind::ExpectedClosingParenthesis,
+                )?;
+                ExpressionKind::Length(Box::new(array))
+            }
+            TokenType::Input | TokenType::ReadInt => {
+                self.consume();
+                let name = match token.token_type {
+                    TokenType::Input => "input",
+                    _ => "read_int",
+                };
+                self.expect(
+                    TokenType::OpenParen,
+                    AstErrorKind::BadBuiltin(name.to_string()),
+                )?;
+                self.expect(
+                    TokenType::CloseParen,
+                    AstErrorKind::ExpectedClosingParenthesis,
+                )?;
+
+                match token.token_type {
+                    TokenType::Input => ExpressionKind::Input,
+                    _ => ExpressionKind::ReadInt,
+                }
+            }
+            TokenType::OpenParen => {
+                self.consume();
+                let expr = self.parse_expression()?;
+                if self
+                    .peek()
+                    .is_none_or(|token| token.token_type != TokenType::CloseParen)
+                {
+                    return Err(self.error(AstErrorKind::ExpectedClosingParenthesis));
+                }
+                self.consume();
+                ExpressionKind::ParenthesisExpression(Box::new(expr))
             }
+            _ => return Err(self.error(AstErrorKind::UnexpectedToken(token.token_type))),
+        };
+        let mut expression = Expression::new(kind, token.span.to(self.previous_span()));
+
+        // Any value can be indexed, `a[i][j]` indexes the result of `a[i]`
+        while self
+            .peek()
+            .is_some_and(|token| token.token_type == TokenType::OpenBracket)
+        {
+            let index = self.parse_index()?;
+            let span = expression.span.to(self.previous_span());
+            expression = Expression::new(
+                ExpressionKind::Index(Box::new(expression), Box::new(index)),
+                span,
+            );
         }
 
-        Ok(left)
+        Ok(expression)
     }
 
-    fn parse_comparision_expression(&mut self) -> Result<Expression, AstError> {
-        let mut left = self.parse_primary()?;
+    // Tokens that can't follow a prefix `!`, because they start the next
+    // statement or end the block
+    fn ends_operand(token_type: &TokenType) -> bool {
+        matches!(
+            token_type,
+            TokenType::Var
+                | TokenType::Print
+                | TokenType::If
+                | TokenType::Else
+                | TokenType::Loop
+                | TokenType::While
+                | TokenType::For
+                | TokenType::Break
+                | TokenType::Continue
+                | TokenType::Fn
+                | TokenType::Return
+                | TokenType::CloseBrace
+        )
+    }
 
-        while let Some(token) = self.peek().cloned() {
-            match token.token_type {
-                TokenType::EqualEqual | TokenType::SemicolonEqual => {
-                    self.consume();
-                    let right = self.parse_primary()?;
-                    left = Expression::BinaryOperation(
-                        Box::new(left),
-                        match token.token_type {
-                            TokenType::EqualEqual => BinaryOperator::Equal,
-                            TokenType::SemicolonEqual => BinaryOperator::NotEqual,
-                            _ => unreachable!(),
-                        },
-                        Box::new(right),
-                    );
+    // Parses the parenthesized, comma separated arguments of a call
+    fn parse_arguments(&mut self) -> Result<Vec<Expression>, AstError> {
+        self.consume();
+
+        let mut arguments = Vec::new();
+        if self
+            .peek()
+            .is_some_and(|token| token.token_type != TokenType::CloseParen)
+        {
+            loop {
+                arguments.push(self.parse_expression()?);
+                match self.peek().map(|token| &token.token_type) {
+                    Some(TokenType::Comma) => self.consume(),
+                    _ => break,
                 }
-                _ => break,
             }
         }
+        self.expect(
+            TokenType::CloseParen,
+            AstErrorKind::ExpectedClosingParenthesis,
+        )?;
 
-        Ok(left)
+        Ok(arguments)
     }
 
-    fn parse_primary(&mut self) -> Result<Expression, AstError> {
-        if let Some(token) = self.peek().cloned() {
-            match &token.token_type {
-                TokenType::Integer(value) => {
-                    self.consume();
-                    Ok(Expression::Integer(*value))
-                }
-                TokenType::Identifier(name) => {
-                    self.consume();
-                    Ok(Expression::Variable(name.to_string()))
-                }
-                TokenType::OpenParen => {
-                    self.consume();
-                    let expr = self.parse_expression()?;
-                    if let Some(token) = self.peek() {
-                        if matches!(token.token_type, TokenType::CloseParen) {
-                            self.consume();
-                            Ok(Expression::ParenthesisExpression(Box::new(expr)))
-                        } else {
-                            return Err(AstError::ExpectedClosingParenthesis);
-                        }
-                    } else {
-                        return Err(AstError::BadIfStatement);
-                    }
-                }
-                _ => return Err(AstError::UnexpectedToken(token.token_type)),
-            }
-        } else {
-            return Err(AstError::ExpectedExpression);
+    // Parses `{ statements }`, reporting error if a bracket is missing
+    fn parse_braced_block(&mut self, error: AstErrorKind) -> Result<Vec<Statement>, AstError> {
+        self.expect(TokenType::OpenBrace, error.clone())?;
+        let statements = self.parse_block();
+        self.expect(TokenType::CloseBrace, error)?;
+
+        Ok(statements)
+    }
+
+    fn expect(&mut self, token_type: TokenType, error: AstErrorKind) -> Result<(), AstError> {
+        if self
+            .peek()
+            .is_none_or(|token| token.token_type != token_type)
+        {
+            return Err(self.error(error));
         }
+        self.consume();
+
+        Ok(())
     }
 
     fn expect_bang(&mut self) -> Result<(), AstError> {
         if self
             .peek()
-            .map_or(true, |token| token.token_type != TokenType::Bang)
+            .is_none_or(|token| token.token_type != TokenType::Bang)
         {
-            return Err(AstError::ExpectedBang);
+            let span = match self.index {
+                0 => self.current_span(),
+                _ => Self::span_after(self.previous_span()),
+            };
+            return Err(AstError {
+                kind: AstErrorKind::ExpectedBang,
+                span,
+            });
         }
         self.consume();
 
@@ -418,15 +1077,95 @@ impl AstParser {
     }
 }
 
+#[derive(Clone)]
+pub struct Expression {
+    pub kind: ExpressionKind,
+    pub span: Span,
+    // Filled in by the type checker
+    pub ty: Option<Type>,
+}
+
+impl Expression {
+    pub fn new(kind: ExpressionKind, span: Span) -> Self {
+        Expression {
+            kind,
+            span,
+            ty: None,
+        }
+    }
+
+    // Every expression has a type once the type checker has run
+    pub fn ty(&self) -> &Type {
+        self.ty
+            .as_ref()
+            .expect("expression was not annotated by the type checker")
+    }
+}
+
+// Spans are left out so that printing the ast stays readable
+impl fmt::Debug for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.kind.fmt(f)
+    }
+}
+
 #[derive(Debug, Clone)]
-pub enum Expression {
+pub enum ExpressionKind {
     Integer(i64),
+    Bool(bool),
+    String(String),
     Variable(String),
+    // The value the variable held before its last assignment
+    Previous(String),
+    UnaryOperation(UnaryOperator, Box<Expression>),
     BinaryOperation(Box<Expression>, BinaryOperator, Box<Expression>),
     ParenthesisExpression(Box<Expression>),
+    // Function name and arguments
+    Call(String, Vec<Expression>),
+    ArrayLiteral(Vec<Expression>),
+    // Array and index
+    Index(Box<Expression>, Box<Expression>),
+    Length(Box<Expression>),
+    // The next line of standard input
+    Input,
+    // The next line of standard input as an integer
+    ReadInt,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Int,
+    Bool,
+    String,
+    // Type of the elements
+    Array(Box<Type>),
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Bool => write!(f, "bool"),
+            Type::String => write!(f, "string"),
+            Type::Array(element) => write!(f, "[{}]", element),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
+pub enum UnaryOperator {
+    Not,
+}
+
+impl fmt::Display for UnaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UnaryOperator::Not => write!(f, "!"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum BinaryOperator {
     Add,
     Subtract,
@@ -434,14 +1173,206 @@ pub enum BinaryOperator {
     Divide,
     Equal,
     NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    // && and || only evaluate the right side if the left side doesn't
+    // decide the result already
+    And,
+    Or,
+}
+
+impl fmt::Display for BinaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BinaryOperator::Add => write!(f, "+"),
+            BinaryOperator::Subtract => write!(f, "-"),
+            BinaryOperator::Multiply => write!(f, "*"),
+            BinaryOperator::Divide => write!(f, "/"),
+            BinaryOperator::Equal => write!(f, "=="),
+            BinaryOperator::NotEqual => write!(f, ";="),
+            BinaryOperator::Less => write!(f, "<"),
+            BinaryOperator::LessEqual => write!(f, "<="),
+            BinaryOperator::Greater => write!(f, ">"),
+            BinaryOperator::GreaterEqual => write!(f, ">="),
+            BinaryOperator::And => write!(f, "&&"),
+            BinaryOperator::Or => write!(f, "||"),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Span,
+}
+
+impl fmt::Debug for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.kind.fmt(f)
+    }
 }
 
 #[derive(Debug, Clone)]
-pub enum Statement {
+pub enum StatementKind {
+    // At the top level this declares a global, which functions can read
+    // before the declaration ran. It holds its zero value until then
     VariableDeclaration(String, Expression),
     Assignment(String, Expression),
-    IfStatement(Expression, Vec<Statement>),
+    // Array variable, the indices into it (more than one for nested arrays)
+    // and the value
+    IndexAssignment(String, Vec<Expression>, Expression),
+    // Condition, then branch and the optional else branch
+    IfStatement(Expression, Vec<Statement>, Option<Vec<Statement>>),
     LoopStatement(Vec<Statement>),
-    BreakStatement,
+    WhileStatement(Expression, Vec<Statement>),
+    // Loop variable, start and (exclusive) end of the range and the body.
+    // Both bounds are evaluated once, before the first iteration, and the
+    // loop variable counts on from whatever value the body left it at
+    ForStatement(String, Expression, Expression, Vec<Statement>),
+    // The optional label is the loop to break out of or continue
+    BreakStatement(Option<String>),
+    ContinueStatement(Option<String>),
+    // Only loops can be labeled
+    LabeledStatement(String, Box<Statement>),
     PrintStatement(Expression),
+    // Name, parameters, return type (None if nothing is returned) and body.
+    // Functions can be called before their declaration
+    FunctionDeclaration(String, Vec<(String, Type)>, Option<Type>, Vec<Statement>),
+    ReturnStatement(Option<Expression>),
+    // A call whose result is thrown away
+    ExpressionStatement(Expression),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    // Parses `print <source>!` and returns the printed expression
+    fn parse_expression(source: &str) -> Expression {
+        let tokens = Lexer::new(format!("print {}!", source))
+            .tokenize()
+            .expect("the source lexes");
+        let (ast, errors) = AstParser::new(tokens).parse();
+        assert!(errors.is_empty(), "syntax errors in `{}`", source);
+
+        match ast
+            .statements
+            .into_iter()
+            .next()
+            .map(|statement| statement.kind)
+        {
+            Some(StatementKind::PrintStatement(expression)) => expression,
+            kind => panic!("expected a print statement, got {:?}", kind),
+        }
+    }
+
+    // Writes the tree with every operation in parentheses, operator first
+    fn shape(expression: &Expression) -> String {
+        match &expression.kind {
+            ExpressionKind::Variable(name) => name.to_string(),
+            ExpressionKind::Integer(value) => value.to_string(),
+            ExpressionKind::UnaryOperation(operator, operand) => {
+                format!("({} {})", operator, shape(operand))
+            }
+            ExpressionKind::BinaryOperation(left, operator, right) => {
+                format!("({} {} {})", operator, shape(left), shape(right))
+            }
+            kind => panic!("no shape for {:?}", kind),
+        }
+    }
+
+    fn assert_shape(source: &str, expected: &str) {
+        assert_eq!(
+            shape(&parse_expression(source)),
+            expected,
+            "for `{}`",
+            source
+        );
+    }
+
+    #[test]
+    fn multiplication_binds_tighter_than_comparison() {
+        assert_shape("a * b == c * d", "(== (* a b) (* c d))");
+        assert_shape("n == 2 * 3", "(== n (* 2 3))");
+    }
+
+    #[test]
+    fn operators_are_left_associative() {
+        assert_shape("a - b - c", "(- (- a b) c)");
+    }
+
+    #[test]
+    fn not_binds_tighter_than_binary_operators() {
+        assert_shape("!a == b", "(== (! a) b)");
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_shape("a || b && c", "(|| a (&& b c))");
+        assert_shape("a && b || c", "(|| (&& a b) c)");
+    }
+
+    #[test]
+    fn equality_binds_tighter_than_and() {
+        assert_shape("a && b == c", "(&& a (== b c))");
+        assert_shape("a ;= b && c", "(&& (;= a b) c)");
+    }
+
+    #[test]
+    fn comparison_binds_tighter_than_equality() {
+        assert_shape("a < b == c", "(== (< a b) c)");
+        assert_shape("a ;= b >= c", "(;= a (>= b c))");
+    }
+
+    #[test]
+    fn addition_binds_tighter_than_comparison() {
+        assert_shape("a + b < c", "(< (+ a b) c)");
+        assert_shape("a > b - c", "(> a (- b c))");
+    }
+
+    #[test]
+    fn multiplication_binds_tighter_than_addition() {
+        assert_shape("a * b + c", "(+ (* a b) c)");
+        assert_shape("a - b / c", "(- a (/ b c))");
+    }
+
+    // A `!` without an operand is reported at the `!`, not at the statement
+    // that comes after it
+    #[test]
+    fn prefix_not_without_operand_is_reported_at_the_bang() {
+        let tokens = Lexer::new("var x = true!\nprint 3 +!\n\n\nif (x) {\n}\n".to_string())
+            .tokenize()
+            .expect("the source lexes");
+        let (_, errors) = AstParser::new(tokens).parse();
+
+        assert!(matches!(
+            errors[..],
+            [AstError {
+                kind: AstErrorKind::ExpectedExpression,
+                ..
+            }]
+        ));
+        assert_eq!((errors[0].span.line, errors[0].span.column), (2, 10));
+    }
+
+    // Columns count characters, so the `é`s take one column each
+    #[test]
+    fn missing_bang_after_non_ascii_text_has_the_right_column() {
+        let tokens = Lexer::new("print \"éé\"".to_string())
+            .tokenize()
+            .expect("the source lexes");
+        let (_, errors) = AstParser::new(tokens).parse();
+
+        assert!(matches!(
+            errors[..],
+            [AstError {
+                kind: AstErrorKind::ExpectedBang,
+                ..
+            }]
+        ));
+        assert_eq!(errors[0].span.column, 11);
+    }
 }
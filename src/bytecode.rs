@@ -0,0 +1,548 @@
+use core::fmt;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::ast::{
+    Ast, BinaryOperator, Expression, ExpressionKind, Statement, StatementKind, UnaryOperator,
+};
+use crate::interpreter::Value;
+use crate::lexer::Span;
+
+// Instructions of the stack based vm. Operands are popped from the stack and
+// results pushed onto it, jumps hold the index of the instruction they jump to
+#[derive(Debug, Clone)]
+pub enum Instruction {
+    // Push a constant
+    Int(i64),
+    Bool(bool),
+    String(Rc<str>),
+    // Discards the value on top of the stack
+    Pop,
+    // Pushes a copy of the value on top of the stack
+    Duplicate,
+    // Push the value of a variable slot, or the value it had before
+    LoadLocal(usize),
+    LoadGlobal(usize),
+    LoadPreviousLocal(usize),
+    LoadPreviousGlobal(usize),
+    // Pop the initial value of a variable, which is its previous value as well
+    DeclareLocal(usize),
+    DeclareGlobal(usize),
+    // Pop the new value of a variable and keep the old one as its previous value
+    StoreLocal(usize),
+    StoreGlobal(usize),
+    // Pops both operands, never && or || since those are jumps
+    Binary(BinaryOperator),
+    Not,
+    Jump(usize),
+    // Pop the condition
+    JumpIfFalse(usize),
+    JumpIfTrue(usize),
+    Print,
+    // Pops the arguments of the function with that index and runs it
+    Call(usize),
+    // Leaves the return value, if any, on top of the stack for the caller
+    Return,
+    // Pops that many elements and pushes an array of them
+    Array(usize),
+    // Pops the index and the array and pushes the element
+    Index,
+    // Pops the value, the index and the array and stores the element
+    StoreIndex,
+    Length,
+    Input,
+    ReadInt,
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instruction::Int(value) => write!(f, "int {}", value),
+            Instruction::Bool(value) => write!(f, "bool {}", value),
+            Instruction::String(value) => write!(f, "string {:?}", value),
+            Instruction::Pop => write!(f, "pop"),
+            Instruction::Duplicate => write!(f, "duplicate"),
+            Instruction::LoadLocal(slot) => write!(f, "load_local {}", slot),
+            Instruction::LoadGlobal(slot) => write!(f, "load_global {}", slot),
+            Instruction::LoadPreviousLocal(slot) => write!(f, "load_previous_local {}", slot),
+            Instruction::LoadPreviousGlobal(slot) => write!(f, "load_previous_global {}", slot),
+            Instruction::DeclareLocal(slot) => write!(f, "declare_local {}", slot),
+            Instruction::DeclareGlobal(slot) => write!(f, "declare_global {}", slot),
+            Instruction::StoreLocal(slot) => write!(f, "store_local {}", slot),
+            Instruction::StoreGlobal(slot) => write!(f, "store_global {}", slot),
+            Instruction::Binary(operator) => write!(f, "binary {}", operator),
+            Instruction::Not => write!(f, "not"),
+            Instruction::Jump(target) => write!(f, "jump {}", target),
+            Instruction::JumpIfFalse(target) => write!(f, "jump_if_false {}", target),
+            Instruction::JumpIfTrue(target) => write!(f, "jump_if_true {}", target),
+            Instruction::Print => write!(f, "print"),
+            Instruction::Call(function) => write!(f, "call {}", function),
+            Instruction::Return => write!(f, "return"),
+            Instruction::Array(length) => write!(f, "array {}", length),
+            Instruction::Index => write!(f, "index"),
+            Instruction::StoreIndex => write!(f, "store_index"),
+            Instruction::Length => write!(f, "length"),
+            Instruction::Input => write!(f, "input"),
+            Instruction::ReadInt => write!(f, "read_int"),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Function {
+    pub name: String,
+    pub parameters: usize,
+    // Names of the local variable slots, starting with the parameters
+    pub locals: Vec<String>,
+    pub code: Vec<Instruction>,
+    // Where every instruction comes from, for runtime errors
+    pub spans: Vec<Span>,
+}
+
+#[derive(Debug)]
+pub struct Program {
+    // Starts with main, which runs the top level statements
+    pub functions: Vec<Function>,
+    // Names and zero values of the globals
+    pub globals: Vec<(String, Value)>,
+}
+
+impl Program {
+    // Lists the instructions of every function with the line they come from
+    pub fn disassemble(&self) -> String {
+        let mut text = String::new();
+
+        for (i, function) in self.functions.iter().enumerate() {
+            if i > 0 {
+                text += "\n";
+            }
+            text += &format!("== {} ==\n", function.name);
+
+            let mut line = 0;
+            for (j, instruction) in function.code.iter().enumerate() {
+                let span = function.spans[j];
+                let source = if span.line == line {
+                    "|".to_string()
+                } else {
+                    span.line.to_string()
+                };
+                line = span.line;
+
+                text += &format!("{:04} {:>4} {}", j, source, instruction);
+                match instruction {
+                    Instruction::LoadLocal(slot)
+                    | Instruction::LoadPreviousLocal(slot)
+                    | Instruction::DeclareLocal(slot)
+                    | Instruction::StoreLocal(slot) => {
+                        text += &format!(" ({})", function.locals[*slot])
+                    }
+                    Instruction::LoadGlobal(slot)
+                    | Instruction::LoadPreviousGlobal(slot)
+                    | Instruction::DeclareGlobal(slot)
+                    | Instruction::StoreGlobal(slot) => {
+                        text += &format!(" ({})", self.globals[*slot].0)
+                    }
+                    Instruction::Call(function) => {
+                        text += &format!(" ({})", self.functions[*function].name)
+                    }
+                    _ => {}
+                }
+                text += "\n";
+            }
+        }
+
+        text
+    }
+}
+
+// Where a variable lives
+enum Slot {
+    Local(usize),
+    Global(usize),
+}
+
+// A loop that is being compiled, with the jumps that still need its addresses
+struct Loop {
+    label: Option<String>,
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+}
+
+// Compiles the ast to bytecode. Runs after the type checker, so every
+// expression is known to be well typed
+#[derive(Default)]
+pub struct Compiler {
+    // Index of every function and whether it returns a value
+    functions: HashMap<String, (usize, bool)>,
+    globals: HashMap<String, usize>,
+    // The function that is being compiled
+    function: Function,
+    // Local variables that are in scope and their slots
+    scope: Vec<(String, usize)>,
+    loops: Vec<Loop>,
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn compile(mut self, ast: &Ast) -> Program {
+        // Functions and globals exist before the first statement runs
+        let mut globals = Vec::new();
+        for statement in ast.statements.iter() {
+            match &statement.kind {
+                StatementKind::FunctionDeclaration(name, _, return_type, _) => {
+                    let index = self.functions.len() + 1;
+                    self.functions
+                        .insert(name.clone(), (index, return_type.is_some()));
+                }
+                StatementKind::VariableDeclaration(name, value) => {
+                    let ty = value.ty();
+                    self.globals.insert(name.clone(), globals.len());
+                    globals.push((name.clone(), Value::zero(ty)));
+                }
+                _ => {}
+            }
+        }
+
+        self.function.name = "main".to_string();
+        for statement in ast.statements.iter() {
+            match &statement.kind {
+                StatementKind::FunctionDeclaration(..) => {}
+                StatementKind::VariableDeclaration(name, value) => {
+                    self.expression(value);
+                    self.emit(
+                        Instruction::DeclareGlobal(self.globals[name]),
+                        statement.span,
+                    );
+                }
+                _ => self.statement(statement),
+            }
+        }
+        let end = ast.statements.last().map(|statement| statement.span);
+        self.emit(Instruction::Return, end.unwrap_or_default());
+
+        let mut functions = vec![std::mem::take(&mut self.function)];
+        for statement in ast.statements.iter() {
+            if let StatementKind::FunctionDeclaration(name, parameters, _, statements) =
+                &statement.kind
+            {
+                self.function.name = name.clone();
+                self.function.parameters = parameters.len();
+                for (parameter, _) in parameters.iter() {
+                    self.declare(parameter);
+                }
+
+                self.block(statements);
+                self.emit(Instruction::Return, statement.span);
+
+                self.scope.clear();
+                functions.push(std::mem::take(&mut self.function));
+            }
+        }
+
+        Program { functions, globals }
+    }
+
+    fn emit(&mut self, instruction: Instruction, span: Span) -> usize {
+        self.function.code.push(instruction);
+        self.function.spans.push(span);
+        self.function.code.len() - 1
+    }
+
+    // Index of the next instruction
+    fn position(&self) -> usize {
+        self.function.code.len()
+    }
+
+    fn patch(&mut self, jump: usize, target: usize) {
+        match &mut self.function.code[jump] {
+            Instruction::Jump(to) | Instruction::JumpIfFalse(to) | Instruction::JumpIfTrue(to) => {
+                *to = target
+            }
+            _ => unreachable!("only jumps are patched"),
+        }
+    }
+
+    // A new local slot that is not in scope by name
+    fn slot(&mut self, name: String) -> usize {
+        self.function.locals.push(name);
+        self.function.locals.len() - 1
+    }
+
+    fn declare(&mut self, name: &str) -> usize {
+        let slot = self.slot(name.to_string());
+        self.scope.push((name.to_string(), slot));
+        slot
+    }
+
+    fn resolve(&self, name: &str) -> Slot {
+        match self
+            .scope
+            .iter()
+            .rev()
+            .find(|(variable, _)| variable == name)
+        {
+            Some((_, slot)) => Slot::Local(*slot),
+            None => Slot::Global(
+                *self
+                    .globals
+                    .get(name)
+                    .expect("semantic analysis checks that variables exist"),
+            ),
+        }
+    }
+
+    // Compiles the statements in a new scope
+    fn block(&mut self, statements: &[Statement]) {
+        let scope = self.scope.len();
+        for statement in statements.iter() {
+            self.statement(statement);
+        }
+        self.scope.truncate(scope);
+    }
+
+    // The loop a break or continue with this label jumps out of
+    fn target(&mut self, label: &Option<String>) -> &mut Loop {
+        match label {
+            Some(label) => self
+                .loops
+                .iter_mut()
+                .rev()
+                .find(|target| target.label.as_ref() == Some(label)),
+            None => self.loops.last_mut(),
+        }
+        .expect("semantic analysis checks that breaks and continues are inside a loop")
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        let span = statement.span;
+        match &statement.kind {
+            StatementKind::VariableDeclaration(name, value) => {
+                self.expression(value);
+                let slot = self.declare(name);
+                self.emit(Instruction::DeclareLocal(slot), span);
+            }
+            StatementKind::Assignment(name, value) => {
+                self.expression(value);
+                let instruction = match self.resolve(name) {
+                    Slot::Local(slot) => Instruction::StoreLocal(slot),
+                    Slot::Global(slot) => Instruction::StoreGlobal(slot),
+                };
+                self.emit(instruction, span);
+            }
+            StatementKind::IndexAssignment(name, indices, value) => {
+                self.load(name, span);
+                let (last, outer) = indices
+                    .split_last()
+                    .expect("an index assignment has at least one index");
+                for index in outer.iter() {
+                    self.expression(index);
+                    self.emit(Instruction::Index, span);
+                }
+                self.expression(last);
+                self.expression(value);
+                self.emit(Instruction::StoreIndex, span);
+            }
+            StatementKind::PrintStatement(value) => {
+                self.expression(value);
+                self.emit(Instruction::Print, span);
+            }
+            StatementKind::ExpressionStatement(value) => {
+                self.expression(value);
+                let has_value = match &value.kind {
+                    ExpressionKind::Call(name, _) => self.functions[name].1,
+                    _ => true,
+                };
+                if has_value {
+                    self.emit(Instruction::Pop, span);
+                }
+            }
+            StatementKind::IfStatement(condition, statements, else_statements) => {
+                self.expression(condition);
+                let skip = self.emit(Instruction::JumpIfFalse(0), span);
+                self.block(statements);
+
+                match else_statements {
+                    Some(else_statements) => {
+                        let end = self.emit(Instruction::Jump(0), span);
+                        self.patch(skip, self.position());
+                        self.block(else_statements);
+                        self.patch(end, self.position());
+                    }
+                    None => self.patch(skip, self.position()),
+                }
+            }
+            StatementKind::LoopStatement(..)
+            | StatementKind::WhileStatement(..)
+            | StatementKind::ForStatement(..) => self.loop_statement(statement, None),
+            StatementKind::LabeledStatement(label, statement) => {
+                self.loop_statement(statement, Some(label))
+            }
+            StatementKind::BreakStatement(label) => {
+                let jump = self.emit(Instruction::Jump(0), span);
+                self.target(label).breaks.push(jump);
+            }
+            StatementKind::ContinueStatement(label) => {
+                let jump = self.emit(Instruction::Jump(0), span);
+                self.target(label).continues.push(jump);
+            }
+            StatementKind::ReturnStatement(value) => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+                self.emit(Instruction::Return, span);
+            }
+            StatementKind::FunctionDeclaration(..) => {
+                unreachable!("functions are only declared at the top level")
+            }
+        }
+    }
+
+    // Compiles a loop
+    fn loop_statement(&mut self, statement: &Statement, label: Option<&String>) {
+        let span = statement.span;
+        let scope = self.scope.len();
+        self.loops.push(Loop {
+            label: label.cloned(),
+            breaks: Vec::new(),
+            continues: Vec::new(),
+        });
+
+        let (start, next) = match &statement.kind {
+            StatementKind::LoopStatement(statements) => {
+                let start = self.position();
+                self.block(statements);
+                (start, start)
+            }
+            StatementKind::WhileStatement(condition, statements) => {
+                let start = self.position();
+                self.expression(condition);
+                let exit = self.emit(Instruction::JumpIfFalse(0), span);
+                self.target(&None).breaks.push(exit);
+                self.block(statements);
+                (start, start)
+            }
+            StatementKind::ForStatement(name, start, end, statements) => {
+                self.expression(start);
+                let counter = self.declare(name);
+                self.emit(Instruction::DeclareLocal(counter), span);
+                self.expression(end);
+                let end = self.slot(format!("{}__end", name));
+                self.emit(Instruction::DeclareLocal(end), span);
+
+                let start = self.position();
+                self.emit(Instruction::LoadLocal(counter), span);
+                self.emit(Instruction::LoadLocal(end), span);
+                self.emit(Instruction::Binary(BinaryOperator::Less), span);
+                let exit = self.emit(Instruction::JumpIfFalse(0), span);
+                self.target(&None).breaks.push(exit);
+                self.block(statements);
+
+                let next = self.position();
+                self.emit(Instruction::LoadLocal(counter), span);
+                self.emit(Instruction::Int(1), span);
+                self.emit(Instruction::Binary(BinaryOperator::Add), span);
+                self.emit(Instruction::StoreLocal(counter), span);
+                (start, next)
+            }
+            _ => unreachable!("only loops can be labeled"),
+        };
+        self.emit(Instruction::Jump(start), span);
+
+        let target = self.loops.pop().expect("the loop was pushed above");
+        let end = self.position();
+        for jump in target.breaks {
+            self.patch(jump, end);
+        }
+        for jump in target.continues {
+            self.patch(jump, next);
+        }
+        self.scope.truncate(scope);
+    }
+
+    fn load(&mut self, name: &str, span: Span) {
+        let instruction = match self.resolve(name) {
+            Slot::Local(slot) => Instruction::LoadLocal(slot),
+            Slot::Global(slot) => Instruction::LoadGlobal(slot),
+        };
+        self.emit(instruction, span);
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        let span = expression.span;
+        match &expression.kind {
+            ExpressionKind::Integer(value) => {
+                self.emit(Instruction::Int(*value), span);
+            }
+            ExpressionKind::Bool(value) => {
+                self.emit(Instruction::Bool(*value), span);
+            }
+            ExpressionKind::String(value) => {
+                self.emit(Instruction::String(value.as_str().into()), span);
+            }
+            ExpressionKind::Variable(name) => self.load(name, span),
+            ExpressionKind::Previous(name) => {
+                let instruction = match self.resolve(name) {
+                    Slot::Local(slot) => Instruction::LoadPreviousLocal(slot),
+                    Slot::Global(slot) => Instruction::LoadPreviousGlobal(slot),
+                };
+                self.emit(instruction, span);
+            }
+            ExpressionKind::ParenthesisExpression(inner) => self.expression(inner),
+            ExpressionKind::UnaryOperation(UnaryOperator::Not, operand) => {
+                self.expression(operand);
+                self.emit(Instruction::Not, span);
+            }
+            // The left side is the result when the right side is skipped
+            ExpressionKind::BinaryOperation(
+                left,
+                operator @ (BinaryOperator::And | BinaryOperator::Or),
+                right,
+            ) => {
+                self.expression(left);
+                self.emit(Instruction::Duplicate, span);
+                let skip = match operator {
+                    BinaryOperator::And => self.emit(Instruction::JumpIfFalse(0), span),
+                    _ => self.emit(Instruction::JumpIfTrue(0), span),
+                };
+                self.emit(Instruction::Pop, span);
+                self.expression(right);
+                self.patch(skip, self.position());
+            }
+            ExpressionKind::BinaryOperation(left, operator, right) => {
+                self.expression(left);
+                self.expression(right);
+                self.emit(Instruction::Binary(operator.clone()), span);
+            }
+            ExpressionKind::Call(name, arguments) => {
+                for argument in arguments.iter() {
+                    self.expression(argument);
+                }
+                self.emit(Instruction::Call(self.functions[name].0), span);
+            }
+            ExpressionKind::ArrayLiteral(elements) => {
+                for element in elements.iter() {
+                    self.expression(element);
+                }
+                self.emit(Instruction::Array(elements.len()), span);
+            }
+            ExpressionKind::Index(array, index) => {
+                self.expression(array);
+                self.expression(index);
+                self.emit(Instruction::Index, span);
+            }
+            ExpressionKind::Length(array) => {
+                self.expression(array);
+                self.emit(Instruction::Length, span);
+            }
+            ExpressionKind::Input => {
+                self.emit(Instruction::Input, span);
+            }
+            ExpressionKind::ReadInt => {
+                self.emit(Instruction::ReadInt, span);
+            }
+        }
+    }
+}
@@ -0,0 +1,159 @@
+pub const USAGE: &str = "Usage: trunkpp <command> [options] <file>
+
+Commands:
+  compile     Compiles the program, to a binary unless --emit says otherwise
+  run         Compiles and runs the program
+  interpret   Runs the program without compiling it to C
+  vm          Compiles the program to bytecode and runs it
+  disasm      Prints the bytecode of the program
+  assemble    Compiles the program to a binary through x86-64 assembly, without C
+  fmt         Rewrites the file in the canonical style
+  ast         Same as compile --emit=ast
+  transpile   Same as compile --emit=c
+  asm         Same as compile --emit=asm
+
+Options:
+  -o <path>         Writes the output to <path>. Text goes to stdout,
+                    binaries are named after the source file and fmt
+                    rewrites the file itself otherwise
+  --emit=<kind>     What compile produces: ast, c, asm or bin (the default)
+  --cc=<compiler>   The C compiler that compiles and links, cc by default
+  --keep-temps      Keeps the temporary directory with the intermediate files
+  -g                Compiles with debug information, so that debuggers show
+                    trunk++ lines, and puts #line directives in --emit=c
+  --check           Makes fmt only check that the file is formatted, without
+                    writing anything
+  -h, --help        Prints this message
+
+Exit codes:
+  0  Success
+  1  The program stopped with a runtime error
+  2  Bad command line arguments
+  3  A file could not be read, written or run
+  4  The program has errors
+  5  The C compiler could not be started or failed
+  6  fmt --check found that the file is not formatted
+`run` exits with the exit code of the program once it ran
+";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Compile,
+    Run,
+    Interpret,
+    Vm,
+    Disasm,
+    Assemble,
+    Fmt,
+}
+
+// What `compile` produces
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Emit {
+    Ast,
+    C,
+    Asm,
+    Bin,
+}
+
+#[derive(Debug)]
+pub struct Options {
+    pub command: Command,
+    pub emit: Emit,
+    pub file: String,
+    pub output: Option<String>,
+    pub cc: String,
+    pub keep_temps: bool,
+    pub debug: bool,
+    pub check: bool,
+}
+
+impl Options {
+    // Parses the arguments after the name of the executable. Options and the
+    // file can come in any order after the command
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut args = args.iter();
+
+        let command = args.next().ok_or("no command given")?;
+        let (command, mut emit) = match command.as_str() {
+            "compile" => (Command::Compile, None),
+            "ast" => (Command::Compile, Some(Emit::Ast)),
+            "transpile" => (Command::Compile, Some(Emit::C)),
+            "asm" => (Command::Compile, Some(Emit::Asm)),
+            "run" => (Command::Run, None),
+            "interpret" => (Command::Interpret, None),
+            "vm" => (Command::Vm, None),
+            "disasm" => (Command::Disasm, None),
+            "assemble" => (Command::Assemble, None),
+            "fmt" => (Command::Fmt, None),
+            command => return Err(format!("unknown command `{}`", command)),
+        };
+        let fixed_emit = emit.is_some();
+
+        let mut file = None;
+        let mut output = None;
+        let mut cc = None;
+        let mut keep_temps = false;
+        let mut debug = false;
+        let mut check = false;
+        while let Some(arg) = args.next() {
+            if arg == "-o" {
+                if matches!(command, Command::Interpret | Command::Vm) {
+                    return Err("-o can not be used when the program is not compiled".to_string());
+                }
+                output = Some(args.next().ok_or("-o needs a path")?.clone());
+            } else if arg == "--keep-temps" {
+                keep_temps = true;
+            } else if arg == "-g" {
+                debug = true;
+            } else if arg == "--check" {
+                if command != Command::Fmt {
+                    return Err("--check can only be used with fmt".to_string());
+                }
+                check = true;
+            } else if let Some(kind) = arg.strip_prefix("--emit=") {
+                if command != Command::Compile || fixed_emit {
+                    return Err("--emit can only be used with compile".to_string());
+                }
+                emit = Some(match kind {
+                    "ast" => Emit::Ast,
+                    "c" => Emit::C,
+                    "asm" => Emit::Asm,
+                    "bin" => Emit::Bin,
+                    kind => {
+                        return Err(format!(
+                            "unknown kind of output `{}`, expected ast, c, asm or bin",
+                            kind
+                        ))
+                    }
+                });
+            } else if let Some(compiler) = arg.strip_prefix("--cc=") {
+                if compiler.is_empty() {
+                    return Err("--cc needs a compiler".to_string());
+                }
+                cc = Some(compiler.to_string());
+            } else if arg.starts_with('-') {
+                return Err(format!("unknown option `{}`", arg));
+            } else if file.is_some() {
+                return Err("only one file can be given".to_string());
+            } else {
+                file = Some(arg.clone());
+            }
+        }
+
+        if check && output.is_some() {
+            return Err("-o can not be used with --check".to_string());
+        }
+
+        Ok(Options {
+            command,
+            emit: emit.unwrap_or(Emit::Bin),
+            file: file.ok_or("no file given")?,
+            output,
+            cc: cc.unwrap_or_else(|| "cc".to_string()),
+            keep_temps,
+            debug,
+            check,
+        })
+    }
+}
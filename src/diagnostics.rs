@@ -0,0 +1,150 @@
+use crate::lexer::Span;
+
+// A single compiler message, rendered rustc-style:
+//
+// error[E0205]: expected `!`
+//  --> examples/fibonacci.tpp:2:10
+//   |
+// 2 | var b = 1
+//   |          ^ expected `!` here
+//   |
+//   = help: every statement in trunk++ ends with a `!`
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub code: &'static str,
+    pub message: String,
+    pub span: Span,
+    pub label: Option<String>,
+    pub notes: Vec<Note>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Note {
+    Note(String),
+    // Note that points at another place in the source, e.g. a previous declaration
+    SpanNote(Span, String),
+    Help(String),
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>, span: Span) -> Self {
+        Diagnostic {
+            code,
+            message: message.into(),
+            span,
+            label: None,
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(Note::Note(note.into()));
+        self
+    }
+
+    pub fn with_span_note(mut self, span: Span, note: impl Into<String>) -> Self {
+        self.notes.push(Note::SpanNote(span, note.into()));
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.notes.push(Note::Help(help.into()));
+        self
+    }
+
+    pub fn render(&self, file_name: &str, source: &str) -> String {
+        // Width of the line number gutter, shared by every snippet in the message
+        let gutter = self
+            .notes
+            .iter()
+            .filter_map(|note| match note {
+                Note::SpanNote(span, _) => Some(span.line),
+                _ => None,
+            })
+            .chain(std::iter::once(self.span.line))
+            .max()
+            .unwrap_or(1)
+            .to_string()
+            .len();
+        let pad = " ".repeat(gutter);
+
+        let mut out = format!("error[{}]: {}\n", self.code, self.message);
+        out += &Self::render_snippet(
+            file_name,
+            source,
+            self.span,
+            '^',
+            self.label.as_deref(),
+            gutter,
+        );
+
+        let mut plain_notes = Vec::new();
+        for note in self.notes.iter() {
+            match note {
+                Note::SpanNote(span, message) => {
+                    out += &format!("{} |\n", pad);
+                    out += &format!("note: {}\n", message);
+                    out += &Self::render_snippet(file_name, source, *span, '-', None, gutter);
+                }
+                Note::Note(message) => plain_notes.push(format!("note: {}", message)),
+                Note::Help(message) => plain_notes.push(format!("help: {}", message)),
+            }
+        }
+
+        if !plain_notes.is_empty() {
+            out += &format!("{} |\n", pad);
+            for note in plain_notes {
+                out += &format!("{} = {}\n", pad, note);
+            }
+        }
+
+        out
+    }
+
+    fn render_snippet(
+        file_name: &str,
+        source: &str,
+        span: Span,
+        marker: char,
+        label: Option<&str>,
+        gutter: usize,
+    ) -> String {
+        let pad = " ".repeat(gutter);
+        let mut out = format!("{}--> {}:{}:{}\n", pad, file_name, span.line, span.column);
+
+        let start = span.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |i| start + i);
+        let line = &source[line_start..line_end];
+
+        // Tabs are expanded so that the carets line up with the source line
+        let expand = |text: &str| text.replace('\t', "    ");
+        let offset = expand(&source[line_start..start]).chars().count();
+        let underlined = expand(&source[start..span.end.clamp(start, line_end)])
+            .chars()
+            .count()
+            .max(1);
+
+        out += &format!("{} |\n", pad);
+        out += &format!("{:>gutter$} | {}\n", span.line, expand(line).trim_end());
+        out += &format!(
+            "{} | {}{}",
+            pad,
+            " ".repeat(offset),
+            marker.to_string().repeat(underlined)
+        );
+        if let Some(label) = label {
+            out += &format!(" {}", label);
+        }
+        out += "\n";
+
+        out
+    }
+}
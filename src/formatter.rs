@@ -0,0 +1,424 @@
+use crate::ast::{Ast, AstParser, Expression, ExpressionKind, Statement, StatementKind, Type};
+use crate::lexer::{Comment, Span, Token, TokenType};
+use crate::writer::CodeWriter;
+
+// Prints a parsed program back as trunk++ in a single canonical style: four
+// spaces per block, one statement per line, spaces around binary operators
+// and at most one blank line where the source had blank lines. Comments are
+// not part of the ast, so they are put back between the statements they were
+// found between. Formatting formatted code changes nothing
+pub struct Formatter<'a> {
+    source: &'a str,
+    // Only needed to find the braces of blocks, which the ast has no spans for
+    tokens: &'a [Token],
+    comments: &'a [Comment],
+    // The first comment that has not been written yet
+    next_comment: usize,
+    // Where the last statement or comment written in the current block ends
+    // in the source, None at the start of a block
+    previous_end: Option<usize>,
+    writer: CodeWriter,
+}
+
+impl<'a> Formatter<'a> {
+    pub fn new(source: &'a str, tokens: &'a [Token], comments: &'a [Comment]) -> Self {
+        Formatter {
+            source,
+            tokens,
+            comments,
+            next_comment: 0,
+            previous_end: None,
+            writer: CodeWriter::new(),
+        }
+    }
+
+    pub fn format(mut self, ast: &Ast) -> String {
+        self.format_block(&ast.statements, self.source.len());
+        self.writer.finish()
+    }
+
+    // Writes the statements of a block and the comments in it, `end` is where
+    // the block is closed in the source
+    fn format_block(&mut self, statements: &[Statement], end: usize) {
+        self.previous_end = None;
+        for statement in statements.iter() {
+            self.comments_before(statement.span.start);
+            self.separate(statement.span.start);
+            self.format_statement(statement, "");
+            self.previous_end = Some(statement.span.end);
+        }
+        self.comments_before(end);
+    }
+
+    // Keeps a blank line the source had between the previous statement or
+    // comment and the one that starts at `start`. Only whitespace counts, a
+    // comment can be written away from the place it was found at
+    fn separate(&mut self, start: usize) {
+        let blank = self
+            .previous_end
+            .and_then(|end| self.source.get(end..start))
+            .is_some_and(|gap| gap.trim().is_empty() && gap.matches('\n').count() > 1);
+
+        if blank {
+            self.writer.blank_line();
+        }
+    }
+
+    // Writes the comments that have not been written yet and start before
+    // `position` on lines of their own
+    fn comments_before(&mut self, position: usize) {
+        while let Some(comment) = self
+            .comments
+            .get(self.next_comment)
+            .filter(|comment| comment.span.start < position)
+        {
+            self.separate(comment.span.start);
+            self.writer.line(&comment.text);
+            self.previous_end = Some(comment.span.end);
+            self.next_comment += 1;
+        }
+    }
+
+    // A comment right after `end` on the same line stays at the end of the
+    // line. Returns it with a space in front, or nothing
+    fn trailing_comment(&mut self, end: usize) -> String {
+        let on_same_line = |comment: &Comment| {
+            self.source
+                .get(end..comment.span.start)
+                .is_some_and(|gap| gap.trim().is_empty() && !gap.contains('\n'))
+        };
+
+        match self.comments.get(self.next_comment) {
+            Some(comment) if on_same_line(comment) => {
+                self.next_comment += 1;
+                format!(" {}", comment.text)
+            }
+            _ => String::new(),
+        }
+    }
+
+    // The first `{` or `}` at or after `position`. Expressions never contain
+    // braces, so that is the brace of the block being looked for
+    fn find_brace(&self, brace: TokenType, position: usize) -> Span {
+        self.tokens
+            .iter()
+            .find(|token| token.span.start >= position && token.token_type == brace)
+            .map(|token| token.span)
+            .expect("the parser checked that every block has braces")
+    }
+
+    // Writes `header {` and indents what comes after it. The `{` is the first
+    // one at or after `position`
+    fn open(&mut self, header: &str, position: usize) {
+        let brace = self.find_brace(TokenType::OpenBrace, position);
+        let trailing = self.trailing_comment(brace.end);
+        self.writer.line(&format!("{} {{{}", header, trailing));
+        self.writer.indent();
+    }
+
+    // Writes the body of a block opened at or after `position` and returns
+    // the span of its `}`
+    fn format_body(&mut self, statements: &[Statement], position: usize) -> Span {
+        let start = statements
+            .last()
+            .map_or(position, |statement| statement.span.end);
+        let close = self.find_brace(TokenType::CloseBrace, start);
+        self.format_block(statements, close.start);
+        close
+    }
+
+    // Dedents and writes the `}` that ends the statement
+    fn close(&mut self, statement: &Statement) {
+        self.writer.dedent();
+        let trailing = self.trailing_comment(statement.span.end);
+        self.writer.line(&format!("}}{}", trailing));
+    }
+
+    // The prefix is the label of a labeled loop
+    fn format_statement(&mut self, statement: &Statement, prefix: &str) {
+        let line = match &statement.kind {
+            StatementKind::VariableDeclaration(name, value) => {
+                format!("var {} = {}!", name, Self::expression(value))
+            }
+            StatementKind::Assignment(name, value) => {
+                format!("{} = {}!", name, Self::expression(value))
+            }
+            StatementKind::IndexAssignment(name, indices, value) => {
+                let indices: String = indices
+                    .iter()
+                    .map(|index| format!("[{}]", Self::expression(index)))
+                    .collect();
+                format!("{}{} = {}!", name, indices, Self::expression(value))
+            }
+            StatementKind::PrintStatement(value) => format!("print {}!", Self::expression(value)),
+            StatementKind::ExpressionStatement(call) => format!("{}!", Self::expression(call)),
+            // A `!` right after `return` would end the statement
+            StatementKind::ReturnStatement(Some(value)) => {
+                let value = Self::expression(value);
+                match value.strip_prefix('!') {
+                    Some(operand) => format!("return not {}!", operand),
+                    None => format!("return {}!", value),
+                }
+            }
+            StatementKind::ReturnStatement(None) => "return!".to_string(),
+            StatementKind::BreakStatement(label) => format!("break{}!", Self::jump_label(label)),
+            StatementKind::ContinueStatement(label) => {
+                format!("continue{}!", Self::jump_label(label))
+            }
+            StatementKind::LabeledStatement(label, statement) => {
+                return self.format_statement(statement, &format!("{}: ", label))
+            }
+            StatementKind::IfStatement(..) => return self.format_if(statement),
+            StatementKind::LoopStatement(statements) => {
+                return self.format_braced(statement, &format!("{}loop", prefix), statements)
+            }
+            StatementKind::WhileStatement(condition, statements) => {
+                let header = format!("{}while ({})", prefix, Self::expression(condition));
+                return self.format_braced(statement, &header, statements);
+            }
+            StatementKind::ForStatement(variable, start, end, statements) => {
+                let header = format!(
+                    "{}for {} in {}..{}",
+                    prefix,
+                    variable,
+                    Self::expression(start),
+                    Self::expression(end)
+                );
+                return self.format_braced(statement, &header, statements);
+            }
+            StatementKind::FunctionDeclaration(name, parameters, return_type, statements) => {
+                let header = Self::function_header(name, parameters, return_type, statements);
+                return self.format_braced(statement, &header, statements);
+            }
+        };
+
+        let trailing = self.trailing_comment(statement.span.end);
+        self.writer.line(&format!("{}{}", line, trailing));
+    }
+
+    // Writes a statement that is a header and a block, which are loops and
+    // functions
+    fn format_braced(&mut self, statement: &Statement, header: &str, statements: &[Statement]) {
+        self.open(header, statement.span.start);
+        self.format_body(statements, statement.span.start);
+        self.close(statement);
+    }
+
+    fn format_if(&mut self, statement: &Statement) {
+        let StatementKind::IfStatement(condition, statements, else_statements) = &statement.kind
+        else {
+            unreachable!("only called for if statements")
+        };
+
+        let header = format!("if ({})", Self::expression(condition));
+        self.open(&header, statement.span.start);
+        let mut close = self.format_body(statements, statement.span.start);
+
+        // An else branch that only holds an if is written as `else if`.
+        // Comments in front of that if go to the end of the branch before it
+        let mut else_statements = else_statements.as_deref();
+        while let Some(statements) = else_statements {
+            match statements {
+                [else_if @ Statement {
+                    kind: StatementKind::IfStatement(condition, statements, next),
+                    ..
+                }] => {
+                    self.comments_before(else_if.span.start);
+                    self.writer.dedent();
+                    let header = format!("}} else if ({})", Self::expression(condition));
+                    self.open(&header, else_if.span.start);
+                    close = self.format_body(statements, else_if.span.start);
+                    else_statements = next.as_deref();
+                }
+                statements => {
+                    self.writer.dedent();
+                    self.open("} else", close.end);
+                    self.format_body(statements, close.end);
+                    break;
+                }
+            }
+        }
+
+        self.close(statement);
+    }
+
+    // Parameters without a type are ints, and a function that returns a value
+    // returns an int without `-> type`, so both are left out for ints
+    fn function_header(
+        name: &str,
+        parameters: &[(String, Type)],
+        return_type: &Option<Type>,
+        statements: &[Statement],
+    ) -> String {
+        let parameters: Vec<String> = parameters
+            .iter()
+            .map(|(parameter, ty)| match ty {
+                Type::Int => parameter.to_string(),
+                ty => format!("{}: {}", parameter, ty),
+            })
+            .collect();
+
+        let return_type = match return_type {
+            Some(Type::Int) if AstParser::returns_value(statements) => String::new(),
+            Some(ty) => format!(" -> {}", ty),
+            None => String::new(),
+        };
+
+        format!("fn {}({}){}", name, parameters.join(", "), return_type)
+    }
+
+    fn jump_label(label: &Option<String>) -> String {
+        match label {
+            Some(label) => format!(" {}", label),
+            None => String::new(),
+        }
+    }
+
+    // Parentheses are kept in the ast, so printing the operators in order
+    // gives back the same grouping
+    fn expression(expression: &Expression) -> String {
+        match &expression.kind {
+            ExpressionKind::Integer(value) => value.to_string(),
+            ExpressionKind::Bool(value) => value.to_string(),
+            ExpressionKind::String(value) => Self::string_literal(value),
+            ExpressionKind::Variable(name) => name.to_string(),
+            ExpressionKind::Previous(name) => format!("prev {}", name),
+            ExpressionKind::UnaryOperation(operator, operand) => {
+                format!("{}{}", operator, Self::expression(operand))
+            }
+            ExpressionKind::BinaryOperation(left, operator, right) => format!(
+                "{} {} {}",
+                Self::expression(left),
+                operator,
+                Self::expression(right)
+            ),
+            ExpressionKind::ParenthesisExpression(expression) => {
+                format!("({})", Self::expression(expression))
+            }
+            ExpressionKind::Call(name, arguments) => {
+                format!("{}({})", name, Self::list(arguments))
+            }
+            ExpressionKind::ArrayLiteral(elements) => format!("[{}]", Self::list(elements)),
+            ExpressionKind::Index(array, index) => {
+                format!("{}[{}]", Self::expression(array), Self::expression(index))
+            }
+            ExpressionKind::Length(array) => format!("len({})", Self::expression(array)),
+            ExpressionKind::Input => "input()".to_string(),
+            ExpressionKind::ReadInt => "read_int()".to_string(),
+        }
+    }
+
+    fn list(expressions: &[Expression]) -> String {
+        let expressions: Vec<String> = expressions.iter().map(Self::expression).collect();
+        expressions.join(", ")
+    }
+
+    // Escapes the characters the lexer unescapes
+    fn string_literal(value: &str) -> String {
+        let mut literal = String::from("\"");
+        for c in value.chars() {
+            match c {
+                '\n' => literal += "\\n",
+                '\t' => literal += "\\t",
+                '"' => literal += "\\\"",
+                '\\' => literal += "\\\\",
+                c => literal.push(c),
+            }
+        }
+        literal.push('"');
+        literal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use std::fs;
+
+    // Comments in every place the formatter has to put them back from
+    const COMMENTS: &str = "// header comment
+
+// second paragraph
+var a = 0! // trailing
+var   b=1!
+
+
+outer: loop { // forever
+    // inside
+    if(a==3){ break outer! } else if (a ;= 5) { // chain
+        a = a+1! // a\"string\" // in a comment
+    }
+    // between
+    else {
+        continue!
+        // end of else
+    }
+    // end of loop
+
+} // after loop
+fn f(x, y: string) -> bool {
+    return not (x == // in an expression
+        1)!
+}
+for i in 0..len([1, 2,3]) { print prev a! }
+// the end
+";
+
+    // Formats the source and returns the output with the comments it had
+    fn format(source: &str) -> (String, Vec<Comment>) {
+        let mut lexer = Lexer::new(source.to_string());
+        let tokens = lexer.tokenize().expect("the source lexes");
+        let (ast, errors) = AstParser::new(tokens.clone()).parse();
+        assert!(errors.is_empty(), "syntax errors in\n{}", source);
+
+        let formatted = Formatter::new(source, &tokens, &lexer.comments).format(&ast);
+        (formatted, lexer.comments)
+    }
+
+    fn assert_idempotent(name: &str, source: &str) {
+        let (formatted, comments) = format(source);
+        let (reformatted, _) = format(&formatted);
+        assert_eq!(
+            formatted, reformatted,
+            "formatting {} twice changes it",
+            name
+        );
+
+        for comment in comments.iter() {
+            assert!(
+                formatted.contains(&comment.text),
+                "formatting {} loses `{}`",
+                name,
+                comment.text
+            );
+        }
+    }
+
+    #[test]
+    fn examples_are_formatted_idempotently() {
+        for entry in fs::read_dir("examples").expect("the examples exist") {
+            let path = entry.expect("the examples can be listed").path();
+            if path.extension().is_some_and(|extension| extension == "tpp") {
+                let source = fs::read_to_string(&path).expect("the example can be read");
+                assert_idempotent(&path.display().to_string(), &source);
+            }
+        }
+    }
+
+    #[test]
+    fn comments_are_formatted_idempotently() {
+        assert_idempotent("the comments", COMMENTS);
+    }
+
+    #[test]
+    fn comments_keep_their_places() {
+        let (formatted, _) = format(COMMENTS);
+        assert!(formatted.starts_with("// header comment\n\n// second paragraph\n"));
+        assert!(formatted.contains("var a = 0! // trailing\n"));
+        assert!(formatted.contains("outer: loop { // forever\n"));
+        assert!(formatted.contains("} else if (a ;= 5) { // chain\n"));
+        assert!(formatted.contains("} // after loop\n"));
+        assert!(formatted.ends_with("// the end\n"));
+    }
+}
@@ -0,0 +1,552 @@
+use core::fmt;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::rc::Rc;
+
+use crate::ast::{
+    Ast, BinaryOperator, Expression, ExpressionKind, Statement, StatementKind, Type, UnaryOperator,
+};
+use crate::lexer::Span;
+
+// Every trunk++ call is a handful of nested Rust calls, so the interpreter
+// needs a lot more stack than the C program to recurse as deep
+const STACK_SIZE: usize = 1 << 30;
+
+// Errors that stop a running program. They read the same as the ones the
+// transpiled C program prints
+#[derive(Debug)]
+pub enum RuntimeErrorKind {
+    // Index and length of the array
+    IndexOutOfBounds(i64, usize),
+    InputEnded,
+    // The line that was read instead
+    NotAnInteger(String),
+    // Crashes the C program instead
+    DivisionByZero,
+}
+
+#[derive(Debug)]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    pub span: Span,
+}
+
+impl fmt::Display for RuntimeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuntimeErrorKind::IndexOutOfBounds(index, length) => write!(
+                f,
+                "index {} is out of bounds for an array of length {}",
+                index, length
+            ),
+            RuntimeErrorKind::InputEnded => {
+                write!(f, "expected an integer but standard input ended")
+            }
+            RuntimeErrorKind::NotAnInteger(text) => {
+                write!(f, "expected an integer but read \"{}\"", text)
+            }
+            RuntimeErrorKind::DivisionByZero => write!(f, "division by zero"),
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} on line {}", self.kind, self.span.line)
+    }
+}
+
+// Runtime values, shared with the bytecode vm
+#[derive(Debug, Clone)]
+pub enum Value {
+    Int(i64),
+    Bool(bool),
+    String(Rc<str>),
+    // Arrays are shared between every variable that holds them
+    Array(Rc<RefCell<Vec<Value>>>),
+}
+
+impl Value {
+    // Value of a global before its declaration has run
+    pub fn zero(ty: &Type) -> Self {
+        match ty {
+            Type::Int => Value::Int(0),
+            Type::Bool => Value::Bool(false),
+            Type::String => Value::String("".into()),
+            Type::Array(_) => Value::Array(Rc::new(RefCell::new(Vec::new()))),
+        }
+    }
+
+    pub fn int(&self) -> i64 {
+        match self {
+            Value::Int(value) => *value,
+            _ => unreachable!("the type checker makes sure this is an int"),
+        }
+    }
+
+    pub fn bool(&self) -> bool {
+        match self {
+            Value::Bool(value) => *value,
+            _ => unreachable!("the type checker makes sure this is a bool"),
+        }
+    }
+
+    pub fn array(&self) -> Rc<RefCell<Vec<Value>>> {
+        match self {
+            Value::Array(elements) => elements.clone(),
+            _ => unreachable!("the type checker makes sure this is an array"),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Int(value) => write!(f, "{}", value),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::String(value) => write!(f, "{}", value),
+            Value::Array(_) => unreachable!("the type checker rejects printing arrays"),
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Int(left), Value::Int(right)) => left == right,
+            (Value::Bool(left), Value::Bool(right)) => left == right,
+            (Value::String(left), Value::String(right)) => left == right,
+            _ => unreachable!("the type checker only allows comparing equal types"),
+        }
+    }
+}
+
+// A variable and the value it had before its last assignment
+#[derive(Debug, Clone)]
+pub struct Variable {
+    pub value: Value,
+    pub previous: Value,
+}
+
+impl Variable {
+    pub fn new(value: Value) -> Self {
+        Variable {
+            previous: value.clone(),
+            value,
+        }
+    }
+
+    pub fn assign(&mut self, value: Value) {
+        self.previous = std::mem::replace(&mut self.value, value);
+    }
+}
+
+// How a statement finished, everything but Normal unwinds the enclosing
+// statements until a loop or function call handles it
+enum Flow {
+    Normal,
+    Break(Option<String>),
+    Continue(Option<String>),
+    Return(Option<Value>),
+}
+
+// What a loop does next after running its body
+enum LoopStep {
+    Next,
+    Exit,
+    // Jumps to an outer loop or returns from the function
+    Unwind(Flow),
+}
+
+// Runs the ast directly, with the same behaviour as the transpiled C program.
+// Runs after the type checker, so every expression is known to be well typed
+#[derive(Default)]
+pub struct Interpreter<'a> {
+    // Parameter names and body of every function
+    functions: HashMap<&'a str, (Vec<&'a str>, &'a [Statement])>,
+    globals: HashMap<&'a str, Variable>,
+    // Variables of the function that is running, or of main. They are
+    // removed again at the end of the block that declared them
+    locals: Vec<(&'a str, Variable)>,
+}
+
+impl<'a> Interpreter<'a> {
+    // Runs the program on a thread of its own, which gets a large stack
+    pub fn run(ast: &Ast) -> Result<(), RuntimeError> {
+        std::thread::scope(|scope| {
+            std::thread::Builder::new()
+                .stack_size(STACK_SIZE)
+                .spawn_scoped(scope, || Interpreter::default().run_program(ast))
+                .expect("Failed to start the interpreter thread")
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+        })
+    }
+
+    fn run_program(mut self, ast: &'a Ast) -> Result<(), RuntimeError> {
+        // Functions and globals exist before the first statement runs
+        for statement in ast.statements.iter() {
+            match &statement.kind {
+                StatementKind::FunctionDeclaration(name, parameters, _, statements) => {
+                    let parameters = parameters
+                        .iter()
+                        .map(|(parameter, _)| parameter.as_str())
+                        .collect();
+                    self.functions.insert(name, (parameters, statements));
+                }
+                StatementKind::VariableDeclaration(name, value) => {
+                    let ty = value.ty();
+                    self.globals.insert(name, Variable::new(Value::zero(ty)));
+                }
+                _ => {}
+            }
+        }
+
+        for statement in ast.statements.iter() {
+            match &statement.kind {
+                StatementKind::FunctionDeclaration(..) => {}
+                StatementKind::VariableDeclaration(name, value) => {
+                    let value = self.evaluate(value)?;
+                    self.globals.insert(name, Variable::new(value));
+                }
+                _ => {
+                    self.execute(statement)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn variable(&mut self, name: &str) -> &mut Variable {
+        match self
+            .locals
+            .iter_mut()
+            .rev()
+            .find(|(variable, _)| *variable == name)
+        {
+            Some((_, variable)) => variable,
+            None => self
+                .globals
+                .get_mut(name)
+                .expect("semantic analysis checks that variables exist"),
+        }
+    }
+
+    fn evaluate(&mut self, expression: &'a Expression) -> Result<Value, RuntimeError> {
+        Ok(match &expression.kind {
+            ExpressionKind::Integer(value) => Value::Int(*value),
+            ExpressionKind::Bool(value) => Value::Bool(*value),
+            ExpressionKind::String(value) => Value::String(value.as_str().into()),
+            ExpressionKind::Variable(name) => self.variable(name).value.clone(),
+            ExpressionKind::Previous(name) => self.variable(name).previous.clone(),
+            ExpressionKind::ParenthesisExpression(inner) => self.evaluate(inner)?,
+            ExpressionKind::UnaryOperation(UnaryOperator::Not, operand) => {
+                Value::Bool(!self.evaluate(operand)?.bool())
+            }
+            ExpressionKind::BinaryOperation(left, operator, right) => {
+                self.evaluate_binary_operation(expression, left, operator, right)?
+            }
+            ExpressionKind::Call(name, arguments) => self
+                .call(name, arguments)?
+                .expect("the type checker rejects using calls without a value"),
+            ExpressionKind::ArrayLiteral(elements) => {
+                let elements = elements
+                    .iter()
+                    .map(|element| self.evaluate(element))
+                    .collect::<Result<Vec<Value>, RuntimeError>>()?;
+                Value::Array(Rc::new(RefCell::new(elements)))
+            }
+            ExpressionKind::Index(array, index) => {
+                let array = self.evaluate(array)?.array();
+                let index = self.evaluate(index)?.int();
+                let position = Self::position(&array, index, expression.span)?;
+                let element = array.borrow()[position].clone();
+                element
+            }
+            ExpressionKind::Length(array) => {
+                Value::Int(self.evaluate(array)?.array().borrow().len() as i64)
+            }
+            ExpressionKind::Input => Self::input(),
+            ExpressionKind::ReadInt => Self::read_int(expression.span)?,
+        })
+    }
+
+    fn evaluate_binary_operation(
+        &mut self,
+        expression: &Expression,
+        left: &'a Expression,
+        operator: &BinaryOperator,
+        right: &'a Expression,
+    ) -> Result<Value, RuntimeError> {
+        let left = self.evaluate(left)?;
+
+        match operator {
+            BinaryOperator::And if !left.bool() => return Ok(Value::Bool(false)),
+            BinaryOperator::Or if left.bool() => return Ok(Value::Bool(true)),
+            BinaryOperator::And | BinaryOperator::Or => return self.evaluate(right),
+            _ => {}
+        }
+
+        let right = self.evaluate(right)?;
+        Self::apply(operator, left, right, expression.span)
+    }
+
+    // Applies an operator other than && and ||. Integers wrap around on
+    // overflow, like the C program does in practice
+    pub fn apply(
+        operator: &BinaryOperator,
+        left: Value,
+        right: Value,
+        span: Span,
+    ) -> Result<Value, RuntimeError> {
+        Ok(match operator {
+            BinaryOperator::Add => Value::Int(left.int().wrapping_add(right.int())),
+            BinaryOperator::Subtract => Value::Int(left.int().wrapping_sub(right.int())),
+            BinaryOperator::Multiply => Value::Int(left.int().wrapping_mul(right.int())),
+            BinaryOperator::Divide => match right.int() {
+                0 => {
+                    return Err(RuntimeError {
+                        kind: RuntimeErrorKind::DivisionByZero,
+                        span,
+                    })
+                }
+                divisor => Value::Int(left.int().wrapping_div(divisor)),
+            },
+            BinaryOperator::Equal => Value::Bool(left == right),
+            BinaryOperator::NotEqual => Value::Bool(left != right),
+            BinaryOperator::Less => Value::Bool(left.int() < right.int()),
+            BinaryOperator::LessEqual => Value::Bool(left.int() <= right.int()),
+            BinaryOperator::Greater => Value::Bool(left.int() > right.int()),
+            BinaryOperator::GreaterEqual => Value::Bool(left.int() >= right.int()),
+            BinaryOperator::And | BinaryOperator::Or => {
+                unreachable!("&& and || are evaluated lazily")
+            }
+        })
+    }
+
+    // Checks the index against the bounds of the array
+    pub fn position(
+        array: &Rc<RefCell<Vec<Value>>>,
+        index: i64,
+        span: Span,
+    ) -> Result<usize, RuntimeError> {
+        let length = array.borrow().len();
+        match usize::try_from(index) {
+            Ok(position) if position < length => Ok(position),
+            _ => Err(RuntimeError {
+                kind: RuntimeErrorKind::IndexOutOfBounds(index, length),
+                span,
+            }),
+        }
+    }
+
+    // The next line of standard input, or an empty string once it has ended
+    pub fn input() -> Value {
+        Value::String(Self::read_line().unwrap_or_default().into())
+    }
+
+    pub fn read_int(span: Span) -> Result<Value, RuntimeError> {
+        let Some(line) = Self::read_line() else {
+            return Err(RuntimeError {
+                kind: RuntimeErrorKind::InputEnded,
+                span,
+            });
+        };
+
+        match line.trim().parse() {
+            Ok(value) => Ok(Value::Int(value)),
+            Err(_) => Err(RuntimeError {
+                kind: RuntimeErrorKind::NotAnInteger(line),
+                span,
+            }),
+        }
+    }
+
+    // The next line of standard input without its line break, or None once
+    // standard input has ended
+    fn read_line() -> Option<String> {
+        let mut line = Vec::new();
+        match std::io::stdin().lock().read_until(b'\n', &mut line) {
+            Ok(0) | Err(_) => return None,
+            Ok(_) => {}
+        }
+
+        if line.ends_with(b"\n") {
+            line.pop();
+        }
+        if line.ends_with(b"\r") {
+            line.pop();
+        }
+
+        Some(String::from_utf8_lossy(&line).into_owned())
+    }
+
+    // Runs a function and returns its return value, if it has one
+    fn call(
+        &mut self,
+        name: &str,
+        arguments: &'a [Expression],
+    ) -> Result<Option<Value>, RuntimeError> {
+        let (parameters, statements) = self.functions[name].clone();
+
+        let mut locals = Vec::new();
+        for (parameter, argument) in parameters.into_iter().zip(arguments) {
+            locals.push((parameter, Variable::new(self.evaluate(argument)?)));
+        }
+
+        let caller_locals = std::mem::replace(&mut self.locals, locals);
+        let flow = self.execute_block(statements);
+        self.locals = caller_locals;
+
+        match flow? {
+            Flow::Return(value) => Ok(value),
+            _ => Ok(None),
+        }
+    }
+
+    // Runs the statements in a new scope
+    fn execute_block(&mut self, statements: &'a [Statement]) -> Result<Flow, RuntimeError> {
+        let scope = self.locals.len();
+
+        let mut flow = Flow::Normal;
+        for statement in statements.iter() {
+            flow = self.execute(statement)?;
+            if !matches!(flow, Flow::Normal) {
+                break;
+            }
+        }
+
+        self.locals.truncate(scope);
+        Ok(flow)
+    }
+
+    fn execute(&mut self, statement: &'a Statement) -> Result<Flow, RuntimeError> {
+        match &statement.kind {
+            StatementKind::VariableDeclaration(name, value) => {
+                let value = self.evaluate(value)?;
+                self.locals.push((name, Variable::new(value)));
+            }
+            StatementKind::Assignment(name, value) => {
+                let value = self.evaluate(value)?;
+                self.variable(name).assign(value);
+            }
+            StatementKind::IndexAssignment(name, indices, value) => {
+                let mut array = self.variable(name).value.array();
+                for (i, index) in indices.iter().enumerate() {
+                    let index = self.evaluate(index)?;
+                    let position = Self::position(&array, index.int(), statement.span)?;
+
+                    if i + 1 == indices.len() {
+                        let value = self.evaluate(value)?;
+                        array.borrow_mut()[position] = value;
+                    } else {
+                        let element = array.borrow()[position].array();
+                        array = element;
+                    }
+                }
+            }
+            StatementKind::PrintStatement(value) => {
+                println!("{}", self.evaluate(value)?);
+            }
+            StatementKind::ExpressionStatement(value) => match &value.kind {
+                ExpressionKind::Call(name, arguments) => {
+                    self.call(name, arguments)?;
+                }
+                _ => {
+                    self.evaluate(value)?;
+                }
+            },
+            StatementKind::IfStatement(condition, statements, else_statements) => {
+                if self.evaluate(condition)?.bool() {
+                    return self.execute_block(statements);
+                } else if let Some(else_statements) = else_statements {
+                    return self.execute_block(else_statements);
+                }
+            }
+            StatementKind::LoopStatement(..)
+            | StatementKind::WhileStatement(..)
+            | StatementKind::ForStatement(..) => return self.execute_loop(statement, None),
+            StatementKind::LabeledStatement(label, statement) => {
+                return self.execute_loop(statement, Some(label))
+            }
+            StatementKind::BreakStatement(label) => return Ok(Flow::Break(label.clone())),
+            StatementKind::ContinueStatement(label) => return Ok(Flow::Continue(label.clone())),
+            StatementKind::ReturnStatement(value) => {
+                let value = match value {
+                    Some(value) => Some(self.evaluate(value)?),
+                    None => None,
+                };
+                return Ok(Flow::Return(value));
+            }
+            StatementKind::FunctionDeclaration(..) => {
+                unreachable!("functions are only declared at the top level")
+            }
+        }
+
+        Ok(Flow::Normal)
+    }
+
+    // Runs a loop
+    fn execute_loop(
+        &mut self,
+        statement: &'a Statement,
+        label: Option<&str>,
+    ) -> Result<Flow, RuntimeError> {
+        let scope = self.locals.len();
+
+        let (condition, end, statements) = match &statement.kind {
+            StatementKind::LoopStatement(statements) => (None, None, statements),
+            StatementKind::WhileStatement(condition, statements) => {
+                (Some(condition), None, statements)
+            }
+            StatementKind::ForStatement(name, start, end, statements) => {
+                let start = self.evaluate(start)?;
+                let end = self.evaluate(end)?.int();
+                self.locals.push((name, Variable::new(start)));
+                (None, Some(end), statements)
+            }
+            _ => unreachable!("only loops can be labeled"),
+        };
+
+        let flow = loop {
+            if let Some(condition) = condition {
+                if !self.evaluate(condition)?.bool() {
+                    break Flow::Normal;
+                }
+            }
+            if let Some(end) = end {
+                if self.locals[scope].1.value.int() >= end {
+                    break Flow::Normal;
+                }
+            }
+
+            match Self::loop_step(self.execute_block(statements)?, label) {
+                LoopStep::Next => {}
+                LoopStep::Exit => break Flow::Normal,
+                LoopStep::Unwind(flow) => break flow,
+            }
+
+            if end.is_some() {
+                let counter = &mut self.locals[scope].1;
+                let next = counter.value.int().wrapping_add(1);
+                counter.assign(Value::Int(next));
+            }
+        };
+
+        self.locals.truncate(scope);
+        Ok(flow)
+    }
+
+    // Decides what a loop with the given label does after its body finished
+    fn loop_step(flow: Flow, label: Option<&str>) -> LoopStep {
+        match flow {
+            Flow::Normal | Flow::Continue(None) => LoopStep::Next,
+            Flow::Break(None) => LoopStep::Exit,
+            Flow::Continue(Some(target)) if label == Some(target.as_str()) => LoopStep::Next,
+            Flow::Break(Some(target)) if label == Some(target.as_str()) => LoopStep::Exit,
+            flow => LoopStep::Unwind(flow),
+        }
+    }
+}
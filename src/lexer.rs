@@ -1,3 +1,7 @@
+use core::fmt;
+
+use crate::diagnostics::Diagnostic;
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum TokenType {
     // Simple
@@ -10,99 +14,345 @@ pub enum TokenType {
     CloseParen,
     OpenBrace,
     CloseBrace,
+    OpenBracket,
+    CloseBracket,
+    DotDot,
+    Colon,
+    Comma,
+    Arrow,
 
     // Complex
     Equal,
     EqualEqual,
     Semicolon,
     SemicolonEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    AmpersandAmpersand,
+    PipePipe,
     Identifier(String),
     Integer(i64),
-    Bool(bool),     // TODO if i have time
-    String(String), // TODO if i have time
+    Bool(bool),
+    String(String),
 
     // Keywords
     Var,
     Print,
     If,
-    Previous, // TODO if i have time
+    Else,
+    Previous,
     Loop,
+    While,
+    For,
+    In,
     Break,
+    Continue,
+    Not,
+    Fn,
+    Return,
+    Len,
+    Input,
+    ReadInt,
 
     Eof,
-    Invalid,
 }
 
+impl fmt::Display for TokenType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenType::Plus => write!(f, "`+`"),
+            TokenType::Minus => write!(f, "`-`"),
+            TokenType::Star => write!(f, "`*`"),
+            TokenType::Slash => write!(f, "`/`"),
+            TokenType::Bang => write!(f, "`!`"),
+            TokenType::OpenParen => write!(f, "`(`"),
+            TokenType::CloseParen => write!(f, "`)`"),
+            TokenType::OpenBrace => write!(f, "`{{`"),
+            TokenType::CloseBrace => write!(f, "`}}`"),
+            TokenType::OpenBracket => write!(f, "`[`"),
+            TokenType::CloseBracket => write!(f, "`]`"),
+            TokenType::DotDot => write!(f, "`..`"),
+            TokenType::Colon => write!(f, "`:`"),
+            TokenType::Comma => write!(f, "`,`"),
+            TokenType::Arrow => write!(f, "`->`"),
+            TokenType::Equal => write!(f, "`=`"),
+            TokenType::EqualEqual => write!(f, "`==`"),
+            TokenType::Semicolon => write!(f, "`;`"),
+            TokenType::SemicolonEqual => write!(f, "`;=`"),
+            TokenType::Less => write!(f, "`<`"),
+            TokenType::LessEqual => write!(f, "`<=`"),
+            TokenType::Greater => write!(f, "`>`"),
+            TokenType::GreaterEqual => write!(f, "`>=`"),
+            TokenType::AmpersandAmpersand => write!(f, "`&&`"),
+            TokenType::PipePipe => write!(f, "`||`"),
+            TokenType::Identifier(name) => write!(f, "identifier `{}`", name),
+            TokenType::Integer(value) => write!(f, "integer `{}`", value),
+            TokenType::Bool(value) => write!(f, "`{}`", value),
+            TokenType::String(value) => write!(f, "string {:?}", value),
+            TokenType::Var => write!(f, "keyword `var`"),
+            TokenType::Print => write!(f, "keyword `print`"),
+            TokenType::If => write!(f, "keyword `if`"),
+            TokenType::Else => write!(f, "keyword `else`"),
+            TokenType::Previous => write!(f, "keyword `prev`"),
+            TokenType::Loop => write!(f, "keyword `loop`"),
+            TokenType::While => write!(f, "keyword `while`"),
+            TokenType::For => write!(f, "keyword `for`"),
+            TokenType::In => write!(f, "keyword `in`"),
+            TokenType::Break => write!(f, "keyword `break`"),
+            TokenType::Continue => write!(f, "keyword `continue`"),
+            TokenType::Not => write!(f, "keyword `not`"),
+            TokenType::Fn => write!(f, "keyword `fn`"),
+            TokenType::Return => write!(f, "keyword `return`"),
+            TokenType::Len => write!(f, "keyword `len`"),
+            TokenType::Input => write!(f, "keyword `input`"),
+            TokenType::ReadInt => write!(f, "keyword `read_int`"),
+            TokenType::Eof => write!(f, "end of file"),
+        }
+    }
+}
+
+// A region of the source code. `start` and `end` are byte offsets (end is
+// exclusive), `line` and `column` are 1-based and point at `start`.
+// `end_column` is the column right after the span on the line it ends on,
+// columns count characters and not bytes
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+    pub end_column: usize,
+}
+
+impl Span {
+    // Span covering everything from the start of self to the end of other
+    pub fn to(&self, other: Span) -> Span {
+        let end = match other.end >= self.end {
+            true => other,
+            false => *self,
+        };
+
+        Span {
+            start: self.start,
+            end: end.end,
+            line: self.line,
+            column: self.column,
+            end_column: end.end_column,
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+// Every operator and punctuation token. Longer operators have to come before
+// their prefixes, so that `<=` is not lexed as `<` followed by `=`
+const OPERATORS: &[(&str, TokenType)] = &[
+    ("==", TokenType::EqualEqual),
+    (";=", TokenType::SemicolonEqual),
+    ("<=", TokenType::LessEqual),
+    (">=", TokenType::GreaterEqual),
+    ("&&", TokenType::AmpersandAmpersand),
+    ("||", TokenType::PipePipe),
+    ("..", TokenType::DotDot),
+    ("->", TokenType::Arrow),
+    ("=", TokenType::Equal),
+    (";", TokenType::Semicolon),
+    (":", TokenType::Colon),
+    (",", TokenType::Comma),
+    ("<", TokenType::Less),
+    (">", TokenType::Greater),
+    ("+", TokenType::Plus),
+    ("-", TokenType::Minus),
+    ("*", TokenType::Star),
+    ("/", TokenType::Slash),
+    ("!", TokenType::Bang),
+    ("(", TokenType::OpenParen),
+    (")", TokenType::CloseParen),
+    ("{", TokenType::OpenBrace),
+    ("}", TokenType::CloseBrace),
+    ("[", TokenType::OpenBracket),
+    ("]", TokenType::CloseBracket),
+];
+
 #[derive(Clone, Debug)]
 pub struct Token {
     pub token_type: TokenType,
-    pub index: usize,
+    pub span: Span,
+}
+
+// A `//` comment, which runs until the end of the line. Comments are not
+// tokens, the lexer only keeps them around for the formatter
+#[derive(Clone, Debug)]
+pub struct Comment {
+    // Starts with the `//`, without trailing whitespace
+    pub text: String,
+    pub span: Span,
 }
 
 pub struct Lexer {
     pub code: String,
-    token_index: usize,
+    pub comments: Vec<Comment>,
     chr_index: usize,
+    line: usize,
+    column: usize,
 }
 
 #[derive(Debug)]
-pub enum LexerError {
+pub enum LexerErrorKind {
     InsignificantToken,
     InvalidNumber,
+    // Does not fit in an i64
+    IntegerTooLarge,
+    UnterminatedString,
+    // The character after the backslash
+    InvalidEscape(char),
+    // A character that starts no token
+    UnexpectedCharacter(char),
+}
+
+#[derive(Debug)]
+pub struct LexerError {
+    pub kind: LexerErrorKind,
+    pub span: Span,
+}
+
+impl fmt::Display for LexerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            LexerErrorKind::InsignificantToken => write!(f, "Insignificant token")?,
+            LexerErrorKind::InvalidNumber => write!(f, "Invalid number")?,
+            LexerErrorKind::IntegerTooLarge => write!(f, "Integer too large")?,
+            LexerErrorKind::UnterminatedString => write!(f, "Unterminated string")?,
+            LexerErrorKind::InvalidEscape(c) => write!(f, "Invalid escape sequence \\{}", c)?,
+            LexerErrorKind::UnexpectedCharacter(c) => write!(f, "Unexpected character `{}`", c)?,
+        }
+        write!(f, " at {}", self.span)
+    }
+}
+
+impl LexerError {
+    pub fn diagnostic(&self) -> Diagnostic {
+        match self.kind {
+            LexerErrorKind::InsignificantToken => {
+                Diagnostic::error("E0100", "insignificant token", self.span)
+            }
+            LexerErrorKind::InvalidNumber => {
+                Diagnostic::error("E0101", "invalid number literal", self.span)
+                    .with_label("numbers can only contain digits")
+                    .with_help("identifiers have to start with a letter")
+            }
+            LexerErrorKind::IntegerTooLarge => {
+                Diagnostic::error("E0104", "integer literal is too large", self.span)
+                    .with_label("does not fit in a 64-bit integer")
+                    .with_note(format!("the largest integer is {}", i64::MAX))
+            }
+            LexerErrorKind::UnterminatedString => {
+                Diagnostic::error("E0102", "unterminated string", self.span)
+                    .with_label("string starts here but is never closed")
+                    .with_help("strings have to end with a `\"` on the same line")
+            }
+            LexerErrorKind::InvalidEscape(c) => Diagnostic::error(
+                "E0103",
+                format!("unknown escape sequence `\\{}`", c),
+                self.span,
+            )
+            .with_label("unknown escape")
+            .with_note("supported escapes are `\\n`, `\\t`, `\\\"` and `\\\\`"),
+            LexerErrorKind::UnexpectedCharacter(c) => {
+                Diagnostic::error("E0105", format!("unexpected character `{}`", c), self.span)
+                    .with_label("no token starts with this character")
+            }
+        }
+    }
 }
 
 impl Lexer {
     pub fn new(code: String) -> Self {
         Self {
             code,
+            comments: Vec::new(),
             chr_index: 0,
-            token_index: 0,
+            line: 1,
+            column: 1,
         }
     }
 
-    fn next_chr(&mut self) -> Option<char> {
-        let chr = self.code.chars().nth(self.chr_index);
-        chr
+    fn next_chr(&self) -> Option<char> {
+        self.code[self.chr_index..].chars().next()
     }
 
     fn consume_chr(&mut self) {
-        self.chr_index += 1;
+        if let Some(c) = self.next_chr() {
+            self.chr_index += c.len_utf8();
+            if c == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
+    }
+
+    // Ends the span at the current position
+    fn end_span(&self, span: &mut Span) {
+        span.end = self.chr_index;
+        span.end_column = self.column;
     }
 
     fn parse_token(&mut self) -> Result<Token, LexerError> {
+        let mut span = Span {
+            start: self.chr_index,
+            end: self.chr_index,
+            line: self.line,
+            column: self.column,
+            end_column: self.column,
+        };
+
+        // Comments are skipped like whitespace, they have to be checked for
+        // before operators or they would be lexed as two slashes
+        if self.code[self.chr_index..].starts_with("//") {
+            while self.next_chr().is_some_and(|c| c != '\n') {
+                self.consume_chr();
+            }
+
+            let text = self.code[span.start..self.chr_index].trim_end().to_string();
+            span.end = span.start + text.len();
+            span.end_column = span.column + text.chars().count();
+            self.comments.push(Comment { text, span });
+            return Err(LexerError {
+                kind: LexerErrorKind::InsignificantToken,
+                span,
+            });
+        }
+
+        if let Some(token_type) = self.parse_operator() {
+            self.end_span(&mut span);
+            return Ok(Token { token_type, span });
+        }
+
         let c = self.next_chr();
         self.consume_chr();
 
         let mut token = Token {
-            token_type: TokenType::Invalid,
-            index: self.token_index,
+            token_type: TokenType::Eof,
+            span,
         };
 
         if let Some(c) = c {
             match c {
-                ' ' | '\n' | '\t' => return Err(LexerError::InsignificantToken),
-                '+' => token.token_type = TokenType::Plus,
-                '-' => token.token_type = TokenType::Minus,
-                '*' => token.token_type = TokenType::Star,
-                '/' => token.token_type = TokenType::Slash,
-                '!' => token.token_type = TokenType::Bang,
-                '(' => token.token_type = TokenType::OpenParen,
-                ')' => token.token_type = TokenType::CloseParen,
-                '{' => token.token_type = TokenType::OpenBrace,
-                '}' => token.token_type = TokenType::CloseBrace,
-                '"' => {
-                    let mut data = String::new();
-                    while let Some(next_chr) = self.next_chr() {
-                        self.consume_chr();
-                        if next_chr == '"' {
-                            break;
-                        }
-
-                        data.push(next_chr);
-                    }
-
-                    token.token_type = TokenType::String(data);
+                ' ' | '\n' | '\t' | '\r' => {
+                    return Err(LexerError {
+                        kind: LexerErrorKind::InsignificantToken,
+                        span,
+                    })
                 }
+                '"' => token.token_type = self.parse_string(span)?,
                 'a'..='z' | 'A'..='Z' => {
                     let mut data = c.to_string();
                     while let Some(next_chr) = self.next_chr() {
@@ -116,11 +366,22 @@ impl Lexer {
 
                     token.token_type = match data.as_str() {
                         "if" => TokenType::If,
+                        "else" => TokenType::Else,
                         "print" => TokenType::Print,
                         "prev" => TokenType::Previous,
                         "loop" => TokenType::Loop,
+                        "while" => TokenType::While,
+                        "for" => TokenType::For,
+                        "in" => TokenType::In,
                         "break" => TokenType::Break,
+                        "continue" => TokenType::Continue,
                         "var" => TokenType::Var,
+                        "not" => TokenType::Not,
+                        "fn" => TokenType::Fn,
+                        "return" => TokenType::Return,
+                        "len" => TokenType::Len,
+                        "input" => TokenType::Input,
+                        "read_int" => TokenType::ReadInt,
                         "true" => TokenType::Bool(true),
                         "false" => TokenType::Bool(false),
                         _ => TokenType::Identifier(data),
@@ -130,49 +391,122 @@ impl Lexer {
                     let mut data = c.to_string();
                     while let Some(next_chr) = self.next_chr() {
                         if next_chr.is_alphabetic() || next_chr == '_' {
-                            return Err(LexerError::InvalidNumber);
+                            while self
+                                .next_chr()
+                                .is_some_and(|c| c.is_alphanumeric() || c == '_')
+                            {
+                                self.consume_chr();
+                            }
+                            self.end_span(&mut span);
+                            return Err(LexerError {
+                                kind: LexerErrorKind::InvalidNumber,
+                                span,
+                            });
                         }
 
-                        if !next_chr.is_numeric() {
+                        if !next_chr.is_ascii_digit() {
                             break;
                         }
 
                         self.consume_chr();
                         data.push(next_chr);
                     }
-                    token.token_type = TokenType::Integer(data.parse().unwrap())
-                }
-                '=' | ';' => {
-                    let mut data = c.to_string();
-                    while let Some(next_chr) = self.next_chr() {
-                        if next_chr.is_alphanumeric() || next_chr.is_whitespace() {
-                            break;
+                    token.token_type = match data.parse() {
+                        Ok(value) => TokenType::Integer(value),
+                        Err(_) => {
+                            self.end_span(&mut span);
+                            return Err(LexerError {
+                                kind: LexerErrorKind::IntegerTooLarge,
+                                span,
+                            });
                         }
-
-                        self.consume_chr();
-                        data.push(next_chr);
-                    }
-                    token.token_type = match data.as_str() {
-                        "=" => TokenType::Equal,
-                        "==" => TokenType::EqualEqual,
-                        ";" => TokenType::Semicolon,
-                        ";=" => TokenType::SemicolonEqual,
-                        _ => TokenType::Invalid,
                     }
                 }
-
-                _ => token.token_type = TokenType::Invalid,
+                _ => {
+                    self.end_span(&mut span);
+                    return Err(LexerError {
+                        kind: LexerErrorKind::UnexpectedCharacter(c),
+                        span,
+                    });
+                }
             }
         } else {
             token.token_type = TokenType::Eof
         }
 
-        self.token_index += 1;
+        self.end_span(&mut token.span);
 
         Ok(token)
     }
 
-    pub fn tokenize(&mut self) -> Result<Vec<Token>, String> {
+    // Operators and punctuation are matched against OPERATORS, the first
+    // (and therefore longest) match wins
+    fn parse_operator(&mut self) -> Option<TokenType> {
+        let rest = &self.code[self.chr_index..];
+        let (text, token_type) = OPERATORS.iter().find(|(text, _)| rest.starts_with(text))?;
+
+        for _ in 0..text.len() {
+            self.consume_chr();
+        }
+
+        Some(token_type.clone())
+    }
+
+    // Parses the rest of a string literal after its opening quote
+    fn parse_string(&mut self, span: Span) -> Result<TokenType, LexerError> {
+        let mut data = String::new();
+
+        loop {
+            let escape_start = self.chr_index;
+            match self.next_chr() {
+                None | Some('\n') => {
+                    return Err(LexerError {
+                        kind: LexerErrorKind::UnterminatedString,
+                        span: Span {
+                            end: span.start + 1,
+                            end_column: span.column + 1,
+                            ..span
+                        },
+                    })
+                }
+                Some('"') => {
+                    self.consume_chr();
+                    return Ok(TokenType::String(data));
+                }
+                Some('\\') => {
+                    let column = self.column;
+                    self.consume_chr();
+                    let escaped = self.next_chr();
+                    data.push(match escaped {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some(c) if c != '\n' => {
+                            return Err(LexerError {
+                                kind: LexerErrorKind::InvalidEscape(c),
+                                span: Span {
+                                    start: escape_start,
+                                    end: self.chr_index + c.len_utf8(),
+                                    line: self.line,
+                                    column,
+                                    end_column: self.column + 1,
+                                },
+                            })
+                        }
+                        _ => continue,
+                    });
+                    self.consume_chr();
+                }
+                Some(c) => {
+                    self.consume_chr();
+                    data.push(c);
+                }
+            }
+        }
+    }
+
+    pub fn tokenize(&mut self) -> Result<Vec<Token>, LexerError> {
         let mut tokens: Vec<Token> = Vec::new();
 
         loop {
@@ -184,19 +518,47 @@ impl Lexer {
 
                     tokens.push(next_token)
                 }
-                Err(e) => {
-                    let message: String = match e {
-                        LexerError::InsignificantToken => continue,
-                        _ => {
-                            format!("Error: {:?}. At: {}", e, self.token_index)
-                        }
-                    };
-
-                    return Err(message);
-                }
+                Err(e) => match e.kind {
+                    LexerErrorKind::InsignificantToken => continue,
+                    _ => return Err(e),
+                },
             }
         }
 
         Ok(tokens)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lexer_error(source: &str) -> LexerError {
+        Lexer::new(source.to_string())
+            .tokenize()
+            .expect_err("the source has a lexer error")
+    }
+
+    #[test]
+    fn integer_overflow_is_reported_as_too_large() {
+        let error = lexer_error("var x = 99999999999999999999!");
+        assert!(matches!(error.kind, LexerErrorKind::IntegerTooLarge));
+        assert_eq!((error.span.column, error.span.end_column), (9, 29));
+
+        let tokens = Lexer::new("9223372036854775807".to_string()).tokenize();
+        assert!(tokens.is_ok_and(|tokens| tokens[0].token_type == TokenType::Integer(i64::MAX)));
+    }
+
+    #[test]
+    fn stray_character_is_reported_where_it_is() {
+        let error = lexer_error("print \"é\" @ 3!");
+        assert!(matches!(
+            error.kind,
+            LexerErrorKind::UnexpectedCharacter('@')
+        ));
+        assert_eq!(
+            (error.span.line, error.span.column, error.span.end_column),
+            (1, 11, 12)
+        );
+    }
+}
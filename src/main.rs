@@ -1,132 +1,354 @@
+use assembly::AssemblyGenerator;
 use ast::AstParser;
+use bytecode::Compiler;
+use cli::{Command, Emit, Options, USAGE};
+use core::fmt;
+use formatter::Formatter;
+use interpreter::{Interpreter, RuntimeError};
 use lexer::Lexer;
 use semantic::Semantic;
-use std::fs::remove_file;
-use std::io::Write;
-use std::process::Command;
-use std::{
-    env,
-    fs::{self, File},
-};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{ExitCode, ExitStatus};
+use toolchain::TempDir;
 use transpiler::Transpiler;
+use typechecker::TypeChecker;
+use vm::Vm;
 
+pub mod assembly;
 pub mod ast;
+pub mod bytecode;
+pub mod cli;
+pub mod diagnostics;
+pub mod formatter;
+pub mod interpreter;
 pub mod lexer;
 pub mod semantic;
+pub mod toolchain;
 pub mod transpiler;
+pub mod typechecker;
+pub mod vm;
+pub mod writer;
 
-fn print_error() {
-    println!(
-        r#"
-Usage: viering-compiler <command> <file>
-
-Commands:
-  ast         Generates the AST
-  transpile   Generates C code
-  compile     Compiles the program
-  run         Compies and runs the program
-"#
-    );
+// Why the compiler stopped. Every kind of failure has an exit code of its
+// own, so that scripts can tell them apart
+enum Failure {
+    // The program stopped with a runtime error
+    Runtime(RuntimeError),
+    Usage(String),
+    Io(String),
+    // The program has errors, which were reported already
+    Source(String),
+    Toolchain(String),
+    // fmt --check found a file that is not formatted
+    Unformatted(String),
 }
 
-#[derive(PartialEq)]
-enum Mode {
-    Ast,
-    Transpile,
-    Compile,
-    Run,
+impl Failure {
+    fn exit_code(&self) -> ExitCode {
+        ExitCode::from(match self {
+            Failure::Runtime(_) => 1,
+            Failure::Usage(_) => 2,
+            Failure::Io(_) => 3,
+            Failure::Source(_) => 4,
+            Failure::Toolchain(_) => 5,
+            Failure::Unformatted(_) => 6,
+        })
+    }
 }
 
-fn compile_c_code(code: &str, file_name: &str) {
-    let temp_file = "temp.c";
-    let mut file = File::create(temp_file).unwrap();
-    file.write_all(code.as_bytes()).unwrap();
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Failure::Runtime(err) => write!(f, "error: {}", err),
+            Failure::Usage(message) => write!(
+                f,
+                "error: {}\nRun `trunkpp --help` to see the commands and options",
+                message
+            ),
+            Failure::Io(message) | Failure::Toolchain(message) | Failure::Unformatted(message) => {
+                write!(f, "error: {}", message)
+            }
+            Failure::Source(message) => write!(f, "{}", message),
+        }
+    }
+}
 
-    Command::new("gcc")
-        .arg(temp_file)
-        .arg("-o")
-        .arg(file_name)
-        .output()
-        .expect("Failed to compile, is gcc installed correctly?");
+// Refuses to write output over the source file, whatever way the path to
+// it is written. A path that does not exist yet can not be the source
+fn check_output(options: &Options, output: &Path) -> Result<(), Failure> {
+    let same = match (fs::canonicalize(&options.file), fs::canonicalize(output)) {
+        (Ok(file), Ok(output)) => file == output,
+        _ => false,
+    };
+
+    if same {
+        return Err(Failure::Usage(format!(
+            "the output `{}` would overwrite the source file",
+            output.display()
+        )));
+    }
+
+    Ok(())
+}
 
-    remove_file(temp_file).unwrap();
+// Writes text output to the -o path, or to stdout without one
+fn write_output(options: &Options, text: &str) -> Result<(), Failure> {
+    match &options.output {
+        Some(path) => {
+            check_output(options, Path::new(path))?;
+            fs::write(path, text)
+                .map_err(|err| Failure::Io(format!("could not write `{}`: {}", path, err)))
+        }
+        None => {
+            print!("{}", text);
+            Ok(())
+        }
+    }
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
+// Where a binary goes without -o: next to us, named after the source file.
+// A source file without an extension could be the binary itself, so the
+// binary gets a `.out` extension then
+fn default_binary(file: &str) -> PathBuf {
+    let path = Path::new(file);
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "main".to_string());
 
-    if args.len() != 3 {
-        print_error();
-        return;
+    if path.extension().is_none() {
+        PathBuf::from(format!("{}.out", stem))
+    } else {
+        PathBuf::from(stem)
     }
+}
+
+// Compiles C or assembly code in the temporary directory into a binary
+fn build_binary(
+    options: &Options,
+    temp_dir: &TempDir,
+    code: &str,
+    extension: &str,
+    binary: &Path,
+) -> Result<(), Failure> {
+    check_output(options, binary)?;
 
-    let mode = match args[1].as_str() {
-        "ast" => Mode::Ast,
-        "transpile" => Mode::Transpile,
-        "compile" => Mode::Compile,
-        "run" => Mode::Run,
-        _ => {
-            print_error();
-            return;
+    let source = temp_dir.path().join(format!("main.{}", extension));
+    fs::write(&source, code)
+        .map_err(|err| Failure::Io(format!("could not write `{}`: {}", source.display(), err)))?;
+
+    toolchain::build(&options.cc, &source, binary, options.debug).map_err(Failure::Toolchain)
+}
+
+// Rewrites the file in the canonical style, or writes it to the -o path.
+// With --check nothing is written and the file has to be formatted already
+fn write_formatted(options: &Options, contents: &str, formatted: &str) -> Result<(), Failure> {
+    if options.check {
+        if contents == formatted {
+            return Ok(());
         }
+
+        let line = contents
+            .lines()
+            .zip(formatted.lines())
+            .take_while(|(line, formatted_line)| line == formatted_line)
+            .count()
+            + 1;
+        return Err(Failure::Unformatted(format!(
+            "`{}` is not formatted, the first difference is on line {}",
+            options.file, line
+        )));
+    }
+
+    // An unchanged file is left alone, so that its modification time stays
+    let path = options.output.as_ref().unwrap_or(&options.file);
+    if options.output.is_none() && contents == formatted {
+        return Ok(());
+    }
+
+    fs::write(path, formatted)
+        .map_err(|err| Failure::Io(format!("could not write `{}`: {}", path, err)))
+}
+
+fn temp_dir(options: &Options) -> Result<TempDir, Failure> {
+    TempDir::new(options.keep_temps)
+        .map_err(|err| Failure::Io(format!("could not create a temporary directory: {}", err)))
+}
+
+// Runs the command and returns the exit code, which is the one of the
+// program itself for `run`
+fn drive(options: &Options) -> Result<ExitCode, Failure> {
+    let file_path = &options.file;
+    let contents = fs::read_to_string(file_path)
+        .map_err(|err| Failure::Io(format!("could not read `{}`: {}", file_path, err)))?;
+
+    let mut lexer = Lexer::new(contents.clone());
+    let tokens = lexer.tokenize().map_err(|err| {
+        eprint!("{}", err.diagnostic().render(file_path, &contents));
+        Failure::Source("Aborting due to a lexer error".to_string())
+    })?;
+
+    let (mut ast, errors) = AstParser::new(tokens.clone()).parse();
+    for err in errors.iter() {
+        eprint!("{}", err.diagnostic().render(file_path, &contents));
+    }
+
+    let emit = match options.command {
+        Command::Compile => Some(options.emit),
+        _ => None,
     };
 
-    match fs::read_to_string(args[2].clone()) {
-        Ok(contents) => {
-            let mut lexer = Lexer::new(contents);
-            let tokens = lexer.tokenize();
-
-            match tokens {
-                Ok(tokens) => {
-                    let mut ast_parser = AstParser::new(tokens.clone());
-                    let ast = match ast_parser.parse() {
-                        Ok(ast) => ast,
-                        Err(err) => {
-                            println!("Error during ast construction: {}", err);
-                            return;
-                        }
-                    };
-
-                    if mode == Mode::Ast {
-                        ast.print();
-                        return;
-                    }
-
-                    match Semantic::new(ast.clone()).check() {
-                        Ok(()) => {}
-                        Err(err) => {
-                            println!("Error during semantic analysis: {}", err);
-                            return;
-                        }
-                    }
-
-                    let transpiler = Transpiler::new();
-                    let c_code = transpiler.transpile(ast);
-
-                    if mode == Mode::Transpile {
-                        println!("{}", c_code);
-                        return;
-                    }
-
-                    let file_name = "main";
-                    compile_c_code(&c_code, file_name);
-
-                    if mode == Mode::Compile {
-                        println!("Compiled code to file: \"main\"");
-                        return;
-                    }
-
-                    let output = Command::new(format!("./{}", file_name)).output().unwrap();
-                    print!("{}", String::from_utf8_lossy(&output.stdout));
-                    remove_file("main").unwrap();
-                }
-                Err(err) => {
-                    println!("{}", err)
-                }
+    if emit == Some(Emit::Ast) {
+        write_output(options, &ast.pretty())?;
+    }
+
+    if !errors.is_empty() {
+        return Err(Failure::Source(format!(
+            "Aborting due to {} syntax error(s)",
+            errors.len()
+        )));
+    }
+
+    if emit == Some(Emit::Ast) {
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    // Formatting only needs the syntax, so programs with semantic errors can
+    // be formatted as well
+    if options.command == Command::Fmt {
+        let formatted = Formatter::new(&contents, &tokens, &lexer.comments).format(&ast);
+        write_formatted(options, &contents, &formatted)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let analysis = Semantic::new(ast.clone()).check().map_err(|errors| {
+        for err in errors.iter() {
+            eprint!("{}", err.diagnostic().render(file_path, &contents));
+        }
+        Failure::Source(format!(
+            "Aborting due to {} semantic error(s)",
+            errors.len()
+        ))
+    })?;
+
+    if let Err(errors) = TypeChecker::new().check(&mut ast) {
+        for err in errors.iter() {
+            eprint!("{}", err.diagnostic().render(file_path, &contents));
+        }
+        return Err(Failure::Source(format!(
+            "Aborting due to {} type error(s)",
+            errors.len()
+        )));
+    }
+
+    match options.command {
+        Command::Fmt => unreachable!("formatting stops before semantic analysis"),
+        Command::Interpret => Interpreter::run(&ast).map_err(Failure::Runtime)?,
+        Command::Vm => {
+            let program = Compiler::new().compile(&ast);
+            Vm::new(&program).run().map_err(Failure::Runtime)?
+        }
+        Command::Disasm => {
+            let program = Compiler::new().compile(&ast);
+            write_output(options, &program.disassemble())?
+        }
+        Command::Compile if options.emit == Emit::Asm => {
+            write_output(options, &AssemblyGenerator::new().generate(&ast))?
+        }
+        Command::Assemble => {
+            let assembly = AssemblyGenerator::new().generate(&ast);
+            let binary = match &options.output {
+                Some(path) => PathBuf::from(path),
+                None => default_binary(file_path),
             };
+
+            build_binary(options, &temp_dir(options)?, &assembly, "s", &binary)?;
+            println!("Compiled code to file: \"{}\"", binary.display());
         }
-        Err(_) => {
-            println!("Invalid file path supplied");
+        Command::Compile if options.emit == Emit::C => {
+            let mut transpiler = Transpiler::new(analysis);
+            if options.debug {
+                transpiler = transpiler.line_directives(file_path);
+            }
+
+            let c_code = transpiler.transpile(ast);
+            write_output(options, &format!("{}\n", c_code))?
+        }
+        Command::Compile | Command::Run => {
+            let c_code = Transpiler::new(analysis)
+                .line_directives(file_path)
+                .transpile(ast);
+
+            let temp_dir = temp_dir(options)?;
+            let binary = match (&options.output, options.command) {
+                (Some(path), _) => PathBuf::from(path),
+                (None, Command::Run) => temp_dir.path().join("main"),
+                (None, _) => default_binary(file_path),
+            };
+            build_binary(options, &temp_dir, &c_code, "c", &binary)?;
+
+            if options.command == Command::Compile {
+                println!("Compiled code to file: \"{}\"", binary.display());
+                return Ok(ExitCode::SUCCESS);
+            }
+
+            // The program inherits our stdin, stdout and stderr, so that
+            // it can be used interactively. A relative -o path would be
+            // looked up in PATH without the ./
+            let binary = Path::new(".").join(binary);
+            let status = std::process::Command::new(&binary)
+                .status()
+                .map_err(|err| {
+                    Failure::Io(format!("could not run `{}`: {}", binary.display(), err))
+                })?;
+
+            return Ok(exit_status(status));
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+// Passes on the exit code of the program. A program killed by a signal
+// exits like it would in a shell, with 128 plus the signal
+fn exit_status(status: ExitStatus) -> ExitCode {
+    if let Some(code) = status.code() {
+        return ExitCode::from(code as u8);
+    }
+
+    #[cfg(unix)]
+    if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        eprintln!("error: the program was killed by signal {}", signal);
+        return ExitCode::from(128 + signal as u8);
+    }
+
+    ExitCode::FAILURE
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        print!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+
+    if args.is_empty() {
+        eprint!("{}", USAGE);
+        return Failure::Usage(String::new()).exit_code();
+    }
+
+    let result = Options::parse(&args)
+        .map_err(Failure::Usage)
+        .and_then(|options| drive(&options));
+
+    match result {
+        Ok(code) => code,
+        Err(failure) => {
+            eprintln!("{}", failure);
+            failure.exit_code()
         }
     }
 }
@@ -1,101 +1,592 @@
 use core::fmt;
+use std::collections::HashSet;
 
-use crate::ast::{Ast, Expression, Statement};
+use crate::ast::{Ast, Expression, ExpressionKind, Statement, StatementKind};
+use crate::diagnostics::Diagnostic;
+use crate::lexer::Span;
 
 #[derive(Debug)]
-pub enum SemanticError {
-    VariableAlreadyDeclared(String),
-    UnknownVariable(String),
+pub enum SemanticErrorKind {
+    // Name and where it was first declared
+    VariableAlreadyDeclared(String, Span),
+    // Name and a similarly named variable that is in scope
+    UnknownVariable(String, Option<String>),
     BreakOutsideLoop,
+    ContinueOutsideLoop,
+    UnknownLabel(String),
+    // Label and the span of the enclosing loop that already uses it
+    DuplicateLabel(String, Span),
+    // Name and where it was first declared
+    FunctionAlreadyDeclared(String, Span),
+    // Name and a similarly named function
+    UnknownFunction(String, Option<String>),
+    // Function name, number of parameters and number of arguments
+    WrongArgumentCount(String, usize, usize),
+    // Function and parameter name
+    DuplicateParameter(String, String),
+    ReturnOutsideFunction,
+    MissingReturn(String),
+    NestedFunction,
 }
 
-impl fmt::Display for SemanticError {
+#[derive(Debug)]
+pub struct SemanticError {
+    pub kind: SemanticErrorKind,
+    pub span: Span,
+}
+
+impl fmt::Display for SemanticErrorKind {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            SemanticError::BreakOutsideLoop => write!(f, "Used break outside of for loop"),
-            SemanticError::UnknownVariable(name) => write!(f, "Unknown variable {}", name),
-            SemanticError::VariableAlreadyDeclared(name) => {
+            SemanticErrorKind::BreakOutsideLoop => write!(f, "Used break outside of for loop"),
+            SemanticErrorKind::ContinueOutsideLoop => write!(f, "Used continue outside of loop"),
+            SemanticErrorKind::UnknownLabel(label) => write!(f, "Unknown loop label {}", label),
+            SemanticErrorKind::DuplicateLabel(label, _) => {
+                write!(f, "Loop label {} already used by an outer loop", label)
+            }
+            SemanticErrorKind::UnknownVariable(name, _) => write!(f, "Unknown variable {}", name),
+            SemanticErrorKind::VariableAlreadyDeclared(name, _) => {
                 write!(f, "Variable {} already declared", name)
             }
+            SemanticErrorKind::FunctionAlreadyDeclared(name, _) => {
+                write!(f, "Function {} already declared", name)
+            }
+            SemanticErrorKind::UnknownFunction(name, _) => write!(f, "Unknown function {}", name),
+            SemanticErrorKind::WrongArgumentCount(name, expected, found) => write!(
+                f,
+                "Function {} takes {} arguments but got {}",
+                name, expected, found
+            ),
+            SemanticErrorKind::DuplicateParameter(function, name) => {
+                write!(f, "Function {} has two parameters named {}", function, name)
+            }
+            SemanticErrorKind::ReturnOutsideFunction => {
+                write!(f, "Used return outside of function")
+            }
+            SemanticErrorKind::MissingReturn(name) => {
+                write!(f, "Function {} does not always return a value", name)
+            }
+            SemanticErrorKind::NestedFunction => write!(f, "Function declared inside a block"),
         }
     }
 }
 
+impl fmt::Display for SemanticError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}", self.kind, self.span)
+    }
+}
+
+impl SemanticError {
+    pub fn diagnostic(&self) -> Diagnostic {
+        match &self.kind {
+            SemanticErrorKind::VariableAlreadyDeclared(name, first) => Diagnostic::error(
+                "E0301",
+                format!("variable `{}` is already declared", name),
+                self.span,
+            )
+            .with_label("declared again here")
+            .with_span_note(*first, format!("`{}` was first declared here", name))
+            .with_help(format!("to change the value use `{} = value!`", name)),
+            SemanticErrorKind::UnknownVariable(name, suggestion) => {
+                let diagnostic =
+                    Diagnostic::error("E0302", format!("unknown variable `{}`", name), self.span)
+                        .with_label("not declared in this scope");
+
+                match suggestion {
+                    Some(suggestion) => {
+                        diagnostic.with_help(format!("did you mean `{}`?", suggestion))
+                    }
+                    None => diagnostic,
+                }
+            }
+            SemanticErrorKind::BreakOutsideLoop => {
+                Diagnostic::error("E0303", "`break` outside of a loop", self.span)
+                    .with_label("cannot break out of this")
+            }
+            SemanticErrorKind::ContinueOutsideLoop => {
+                Diagnostic::error("E0304", "`continue` outside of a loop", self.span)
+                    .with_label("cannot continue this")
+            }
+            SemanticErrorKind::UnknownLabel(label) => Diagnostic::error(
+                "E0305",
+                format!("unknown loop label `{}`", label),
+                self.span,
+            )
+            .with_label("no enclosing loop has this label")
+            .with_help(format!("label a loop with `{}: loop {{ ... }}`", label)),
+            SemanticErrorKind::DuplicateLabel(label, outer) => Diagnostic::error(
+                "E0306",
+                format!("loop label `{}` is already in use", label),
+                self.span,
+            )
+            .with_label("label used again here")
+            .with_span_note(*outer, "by this enclosing loop"),
+            SemanticErrorKind::FunctionAlreadyDeclared(name, first) => Diagnostic::error(
+                "E0307",
+                format!("function `{}` is already declared", name),
+                self.span,
+            )
+            .with_label("declared again here")
+            .with_span_note(*first, format!("`{}` was first declared here", name)),
+            SemanticErrorKind::UnknownFunction(name, suggestion) => {
+                let diagnostic =
+                    Diagnostic::error("E0308", format!("unknown function `{}`", name), self.span)
+                        .with_label("no function with this name");
+
+                match suggestion {
+                    Some(suggestion) => {
+                        diagnostic.with_help(format!("did you mean `{}`?", suggestion))
+                    }
+                    None => diagnostic,
+                }
+            }
+            SemanticErrorKind::WrongArgumentCount(name, expected, found) => Diagnostic::error(
+                "E0309",
+                format!(
+                    "`{}` takes {} argument(s) but {} were given",
+                    name, expected, found
+                ),
+                self.span,
+            )
+            .with_label(format!("expected {} argument(s)", expected)),
+            SemanticErrorKind::DuplicateParameter(function, name) => Diagnostic::error(
+                "E0310",
+                format!("parameter `{}` of `{}` is declared twice", name, function),
+                self.span,
+            )
+            .with_label("in this function"),
+            SemanticErrorKind::ReturnOutsideFunction => {
+                Diagnostic::error("E0311", "`return` outside of a function", self.span)
+                    .with_label("cannot return from here")
+            }
+            SemanticErrorKind::MissingReturn(name) => Diagnostic::error(
+                "E0312",
+                format!("`{}` does not return a value on every path", name),
+                self.span,
+            )
+            .with_label("this function can finish without returning")
+            .with_help("add a `return value!` at the end of the function"),
+            SemanticErrorKind::NestedFunction => Diagnostic::error(
+                "E0313",
+                "functions can only be declared at the top level",
+                self.span,
+            )
+            .with_label("declared inside a block"),
+        }
+    }
+}
+
+// Variables visible at some point in the program, with their declaration site
+type Scope = Vec<(String, Span)>;
+
+// What the later passes need to know about the program
+#[derive(Debug, Default)]
+pub struct Analysis {
+    // Variables that are read with `prev`, so their old value has to be kept
+    pub history: HashSet<String>,
+}
+
 pub struct Semantic {
     ast: Ast,
+    errors: Vec<SemanticError>,
+    analysis: Analysis,
+    // Labels of the loops enclosing the statement being checked
+    labels: Vec<(String, Span)>,
+    // Every function with its number of parameters and declaration site
+    functions: Vec<(String, usize, Span)>,
+    inside_function: bool,
 }
 
 impl Semantic {
     pub fn new(ast: Ast) -> Self {
-        Semantic { ast }
+        Semantic {
+            ast,
+            errors: Vec::new(),
+            analysis: Analysis::default(),
+            labels: Vec::new(),
+            functions: Vec::new(),
+            inside_function: false,
+        }
     }
 
-    pub fn check(&self) -> Result<(), SemanticError> {
-        Self::check_statement_block_scope(&self.ast.statements, Vec::new(), false)
-    }
+    // Checks the whole program and returns every error found
+    pub fn check(mut self) -> Result<Analysis, Vec<SemanticError>> {
+        let statements = std::mem::take(&mut self.ast.statements);
+
+        // Functions can be called before they are declared, so all of them
+        // are collected first
+        for statement in statements.iter() {
+            if let StatementKind::FunctionDeclaration(name, parameters, ..) = &statement.kind {
+                match self.functions.iter().find(|(other, ..)| other == name) {
+                    Some((_, _, first)) => self.errors.push(SemanticError {
+                        kind: SemanticErrorKind::FunctionAlreadyDeclared(name.to_string(), *first),
+                        span: statement.span,
+                    }),
+                    None => {
+                        self.functions
+                            .push((name.to_string(), parameters.len(), statement.span))
+                    }
+                }
+            }
+        }
 
-    fn check_expression_scope(
-        expression: &Expression,
-        variables: Vec<String>,
-    ) -> Result<(), SemanticError> {
-        match expression {
-            Expression::Variable(name) => {
-                if !variables.contains(name) {
-                    return Err(SemanticError::UnknownVariable(name.to_string()));
+        // Functions can use the top level variables declared before them
+        let mut globals = Scope::new();
+        for statement in statements.iter() {
+            match statement.kind {
+                StatementKind::FunctionDeclaration(..) => {
+                    self.check_function(statement, globals.clone())
                 }
+                _ => self.check_statement(statement, &mut globals, false),
+            }
+        }
+
+        if self.errors.is_empty() {
+            Ok(self.analysis)
+        } else {
+            // The passes above find errors out of order, the report reads
+            // from the top of the file down
+            self.errors.sort_by_key(|error| error.span.start);
+            Err(self.errors)
+        }
+    }
+
+    fn lookup<'a>(variables: &'a Scope, name: &str) -> Option<&'a Span> {
+        variables
+            .iter()
+            .find(|(variable, _)| variable == name)
+            .map(|(_, span)| span)
+    }
+
+    fn unknown_variable(name: &str, span: Span, variables: &Scope) -> SemanticError {
+        SemanticError {
+            kind: SemanticErrorKind::UnknownVariable(
+                name.to_string(),
+                Self::suggest(name, variables.iter().map(|(variable, _)| variable)),
+            ),
+            span,
+        }
+    }
+
+    // Finds the candidate with the name closest to the misspelled one
+    fn suggest<'a>(name: &str, candidates: impl Iterator<Item = &'a String>) -> Option<String> {
+        let length = name.chars().count();
+        let max_distance = length.max(3) / 3 + 1;
+
+        candidates
+            .map(|candidate| (Self::edit_distance(name, candidate), candidate))
+            .filter(|(distance, _)| *distance <= max_distance && *distance < length)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, candidate)| candidate.to_string())
+    }
+
+    // Levenshtein distance between two strings
+    fn edit_distance(a: &str, b: &str) -> usize {
+        let b: Vec<char> = b.chars().collect();
+        let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+        for (i, a_chr) in a.chars().enumerate() {
+            let mut current = vec![i + 1];
+            for (j, b_chr) in b.iter().enumerate() {
+                let substitution = previous[j] + usize::from(a_chr != *b_chr);
+                current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+            }
+            previous = current;
+        }
+
+        previous[b.len()]
+    }
+
+    fn check_expression_scope(&mut self, expression: &Expression, variables: &Scope) {
+        match &expression.kind {
+            ExpressionKind::Variable(name) | ExpressionKind::Previous(name)
+                if Self::lookup(variables, name).is_none() =>
+            {
+                self.errors
+                    .push(Self::unknown_variable(name, expression.span, variables));
+            }
+            ExpressionKind::Previous(name) => {
+                self.analysis.history.insert(name.to_string());
             }
-            Expression::ParenthesisExpression(inner) => {
-                Self::check_expression_scope(inner, variables)?;
+            ExpressionKind::ParenthesisExpression(inner)
+            | ExpressionKind::UnaryOperation(_, inner)
+            | ExpressionKind::Length(inner) => {
+                self.check_expression_scope(inner, variables);
             }
-            Expression::BinaryOperation(left, _, right) => {
-                Self::check_expression_scope(left, variables.clone())?;
-                Self::check_expression_scope(right, variables)?;
+            ExpressionKind::BinaryOperation(left, _, right)
+            | ExpressionKind::Index(left, right) => {
+                self.check_expression_scope(left, variables);
+                self.check_expression_scope(right, variables);
+            }
+            ExpressionKind::ArrayLiteral(elements) => {
+                for element in elements.iter() {
+                    self.check_expression_scope(element, variables);
+                }
+            }
+            ExpressionKind::Call(name, arguments) => {
+                match self
+                    .functions
+                    .iter()
+                    .find(|(function, ..)| function == name)
+                {
+                    Some((_, parameters, _)) if *parameters != arguments.len() => {
+                        self.errors.push(SemanticError {
+                            kind: SemanticErrorKind::WrongArgumentCount(
+                                name.to_string(),
+                                *parameters,
+                                arguments.len(),
+                            ),
+                            span: expression.span,
+                        })
+                    }
+                    Some(_) => {}
+                    None => self.errors.push(SemanticError {
+                        kind: SemanticErrorKind::UnknownFunction(
+                            name.to_string(),
+                            Self::suggest(
+                                name,
+                                self.functions.iter().map(|(function, ..)| function),
+                            ),
+                        ),
+                        span: expression.span,
+                    }),
+                }
+
+                for argument in arguments.iter() {
+                    self.check_expression_scope(argument, variables);
+                }
             }
             _ => {}
         }
+    }
+
+    // Function bodies see the given globals and their parameters, which may
+    // shadow globals, and start outside of any loop
+    fn check_function(&mut self, function: &Statement, mut variables: Scope) {
+        let StatementKind::FunctionDeclaration(name, parameters, return_type, statements) =
+            &function.kind
+        else {
+            unreachable!("only called on function declarations");
+        };
+
+        for (i, (parameter, _)) in parameters.iter().enumerate() {
+            if parameters[..i].iter().any(|(other, _)| other == parameter) {
+                self.errors.push(SemanticError {
+                    kind: SemanticErrorKind::DuplicateParameter(
+                        name.to_string(),
+                        parameter.to_string(),
+                    ),
+                    span: function.span,
+                });
+            } else {
+                variables.retain(|(variable, _)| variable != parameter);
+                variables.push((parameter.to_string(), function.span));
+            }
+        }
+
+        self.inside_function = true;
+        self.check_statement_block_scope(statements, variables, false);
+        self.inside_function = false;
+
+        if return_type.is_some() && !Self::always_returns(statements) {
+            self.errors.push(SemanticError {
+                kind: SemanticErrorKind::MissingReturn(name.to_string()),
+                span: function.span,
+            });
+        }
+    }
 
-        Ok(())
+    // Whether running the statements always ends in a `return`. A `loop`
+    // that is never broken out of counts as well, since it never finishes
+    fn always_returns(statements: &[Statement]) -> bool {
+        statements.iter().any(|statement| match &statement.kind {
+            StatementKind::ReturnStatement(_) => true,
+            StatementKind::IfStatement(_, statements, Some(else_statements)) => {
+                Self::always_returns(statements) && Self::always_returns(else_statements)
+            }
+            StatementKind::LoopStatement(statements) => !Self::breaks(statements, false),
+            StatementKind::LabeledStatement(_, statement) => {
+                Self::always_returns(std::slice::from_ref(statement))
+            }
+            _ => false,
+        })
+    }
+
+    // Whether the statements can break out of the loop they are in. Labeled
+    // breaks are assumed to leave it, even from inside nested loops
+    fn breaks(statements: &[Statement], nested: bool) -> bool {
+        statements.iter().any(|statement| match &statement.kind {
+            StatementKind::BreakStatement(label) => label.is_some() || !nested,
+            StatementKind::IfStatement(_, statements, else_statements) => {
+                Self::breaks(statements, nested)
+                    || else_statements
+                        .as_deref()
+                        .is_some_and(|statements| Self::breaks(statements, nested))
+            }
+            StatementKind::LoopStatement(statements)
+            | StatementKind::WhileStatement(_, statements)
+            | StatementKind::ForStatement(_, _, _, statements) => Self::breaks(statements, true),
+            StatementKind::LabeledStatement(_, statement) => {
+                Self::breaks(std::slice::from_ref(statement), nested)
+            }
+            _ => false,
+        })
     }
 
     fn check_statement_block_scope(
+        &mut self,
         statements: &[Statement],
-        mut variables: Vec<String>,
+        mut variables: Scope,
         inside_loop: bool,
-    ) -> Result<(), SemanticError> {
+    ) {
         for statement in statements.iter() {
-            match statement {
-                Statement::IfStatement(condition, statements) => {
-                    Self::check_expression_scope(condition, variables.clone())?;
-                    Self::check_statement_block_scope(statements, variables.clone(), inside_loop)?;
+            self.check_statement(statement, &mut variables, inside_loop);
+        }
+    }
+
+    // Checks a single statement, declarations are added to the variables
+    fn check_statement(&mut self, statement: &Statement, variables: &mut Scope, inside_loop: bool) {
+        match &statement.kind {
+            StatementKind::IfStatement(condition, statements, else_statements) => {
+                self.check_expression_scope(condition, variables);
+                self.check_statement_block_scope(statements, variables.clone(), inside_loop);
+                if let Some(else_statements) = else_statements {
+                    self.check_statement_block_scope(
+                        else_statements,
+                        variables.clone(),
+                        inside_loop,
+                    );
                 }
-                Statement::LoopStatement(statements) => {
-                    Self::check_statement_block_scope(statements, variables.clone(), true)?;
+            }
+            StatementKind::LoopStatement(statements) => {
+                self.check_statement_block_scope(statements, variables.clone(), true);
+            }
+            StatementKind::WhileStatement(condition, statements) => {
+                self.check_expression_scope(condition, variables);
+                self.check_statement_block_scope(statements, variables.clone(), true);
+            }
+            StatementKind::ForStatement(name, start, end, statements) => {
+                self.check_expression_scope(start, variables);
+                self.check_expression_scope(end, variables);
+
+                // The loop variable only exists inside the body
+                let mut body_variables = variables.clone();
+                match Self::lookup(variables, name) {
+                    Some(first) => self.errors.push(SemanticError {
+                        kind: SemanticErrorKind::VariableAlreadyDeclared(name.to_string(), *first),
+                        span: statement.span,
+                    }),
+                    None => body_variables.push((name.to_string(), statement.span)),
                 }
-                Statement::VariableDeclaration(name, value) => {
-                    if variables.contains(name) {
-                        return Err(SemanticError::VariableAlreadyDeclared(name.to_string()));
-                    }
+                self.check_statement_block_scope(statements, body_variables, true);
+            }
+            StatementKind::VariableDeclaration(name, value) => {
+                self.check_expression_scope(value, variables);
 
-                    Self::check_expression_scope(value, variables.clone())?;
+                match Self::lookup(variables, name) {
+                    Some(first) => self.errors.push(SemanticError {
+                        kind: SemanticErrorKind::VariableAlreadyDeclared(name.to_string(), *first),
+                        span: statement.span,
+                    }),
+                    None => variables.push((name.to_string(), statement.span)),
+                }
+            }
+            StatementKind::Assignment(name, value) => {
+                if Self::lookup(variables, name).is_none() {
+                    self.errors
+                        .push(Self::unknown_variable(name, statement.span, variables));
+                }
 
-                    variables.push(name.to_string());
+                self.check_expression_scope(value, variables);
+            }
+            StatementKind::IndexAssignment(name, indices, value) => {
+                if Self::lookup(variables, name).is_none() {
+                    self.errors
+                        .push(Self::unknown_variable(name, statement.span, variables));
                 }
-                Statement::Assignment(name, value) => {
-                    if !variables.contains(name) {
-                        return Err(SemanticError::UnknownVariable(name.to_string()));
-                    }
 
-                    Self::check_expression_scope(value, variables.clone())?;
+                for index in indices.iter() {
+                    self.check_expression_scope(index, variables);
+                }
+                self.check_expression_scope(value, variables);
+            }
+            StatementKind::PrintStatement(value) | StatementKind::ExpressionStatement(value) => {
+                self.check_expression_scope(value, variables);
+            }
+            StatementKind::ReturnStatement(value) => {
+                if !self.inside_function {
+                    self.errors.push(SemanticError {
+                        kind: SemanticErrorKind::ReturnOutsideFunction,
+                        span: statement.span,
+                    });
+                }
+                if let Some(value) = value {
+                    self.check_expression_scope(value, variables);
+                }
+            }
+            // Top level functions are checked by check_function
+            StatementKind::FunctionDeclaration(..) => self.errors.push(SemanticError {
+                kind: SemanticErrorKind::NestedFunction,
+                span: statement.span,
+            }),
+            StatementKind::LabeledStatement(label, inner) => {
+                if let Some((_, outer)) = self.labels.iter().find(|(other, _)| other == label) {
+                    self.errors.push(SemanticError {
+                        kind: SemanticErrorKind::DuplicateLabel(label.to_string(), *outer),
+                        span: statement.span,
+                    });
                 }
-                Statement::BreakStatement => {
-                    if !inside_loop {
-                        return Err(SemanticError::BreakOutsideLoop);
+
+                self.labels.push((label.to_string(), statement.span));
+                self.check_statement_block_scope(
+                    std::slice::from_ref(inner),
+                    variables.clone(),
+                    inside_loop,
+                );
+                self.labels.pop();
+            }
+            StatementKind::BreakStatement(label) | StatementKind::ContinueStatement(label) => {
+                if !inside_loop {
+                    self.errors.push(SemanticError {
+                        kind: match statement.kind {
+                            StatementKind::BreakStatement(_) => SemanticErrorKind::BreakOutsideLoop,
+                            _ => SemanticErrorKind::ContinueOutsideLoop,
+                        },
+                        span: statement.span,
+                    });
+                } else if let Some(label) = label {
+                    if !self.labels.iter().any(|(other, _)| other == label) {
+                        self.errors.push(SemanticError {
+                            kind: SemanticErrorKind::UnknownLabel(label.to_string()),
+                            span: statement.span,
+                        });
                     }
                 }
-                _ => {}
             }
         }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::AstParser;
+    use crate::lexer::Lexer;
+
+    #[test]
+    fn errors_are_reported_in_source_order() {
+        let source = "var x = 1!\nvar x = 2!\n\nfn f() {\n}\nfn f() {\n}\n";
+        let tokens = Lexer::new(source.to_string())
+            .tokenize()
+            .expect("the source lexes");
+        let (ast, errors) = AstParser::new(tokens).parse();
+        assert!(errors.is_empty());
 
-        Ok(())
+        let errors = Semantic::new(ast)
+            .check()
+            .expect_err("the source has errors");
+        let lines: Vec<usize> = errors.iter().map(|error| error.span.line).collect();
+        assert_eq!(lines, [2, 6]);
     }
 }
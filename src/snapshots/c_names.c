@@ -0,0 +1,41 @@
+#include <stdbool.h>
+#include <stdio.h>
+
+static void print_bool__rt(bool value) {
+    printf("%s\n", value ? "true" : "false");
+}
+
+long long f__fn(long long double__l);
+long long g__fn(long long x__l__l);
+
+long long EOF__g = 0;
+long long x__g__g = 0;
+
+long long f__fn(long long double__l) {
+    return double__l * 2;
+}
+long long g__fn(long long x__l__l) {
+    return x__l__l + x__g__g;
+}
+
+int main() {
+    EOF__g = 1;
+    while (1) {
+        long long printf__l = 5;
+        printf("%lld\n", (long long)(printf__l));
+        break;
+    }
+    if (true) {
+        long long int__l = f__fn(3); long long int__l__prev = int__l;
+        { long long int__l__next = int__l + EOF__g; int__l__prev = int__l; int__l = int__l__next; }
+        printf("%lld\n", (long long)(int__l__prev));
+    }
+    for (long long errno__l = 0, errno__l__end = 2; errno__l < errno__l__end; errno__l++) {
+        long long strcmp__l = errno__l;
+        bool bool__l = strcmp__l == 1;
+        print_bool__rt(bool__l);
+    }
+    x__g__g = 4;
+    printf("%lld\n", (long long)(g__fn(1)));
+    return 0;
+}
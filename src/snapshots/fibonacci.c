@@ -0,0 +1,23 @@
+#include <stdbool.h>
+#include <stdio.h>
+
+long long a__g = 0;
+long long b__g = 0;
+long long n__g = 0;
+
+int main() {
+    a__g = 0;
+    b__g = 1;
+    n__g = 0;
+    while (1) {
+        if (n__g == 10) {
+            break;
+        }
+        printf("%lld\n", (long long)(b__g));
+        long long c__l = a__g + b__g;
+        a__g = b__g;
+        b__g = c__l;
+        n__g = n__g + 1;
+    }
+    return 0;
+}
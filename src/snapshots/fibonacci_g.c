@@ -0,0 +1,42 @@
+#include <stdbool.h>
+#include <stdio.h>
+
+#line 1 "examples/fibonacci.tpp"
+long long a__g = 0;
+#line 2 "examples/fibonacci.tpp"
+long long b__g = 0;
+#line 3 "examples/fibonacci.tpp"
+long long n__g = 0;
+
+#line 1 "examples/fibonacci.tpp"
+int main() {
+#line 1 "examples/fibonacci.tpp"
+    a__g = 0;
+#line 2 "examples/fibonacci.tpp"
+    b__g = 1;
+#line 3 "examples/fibonacci.tpp"
+    n__g = 0;
+#line 5 "examples/fibonacci.tpp"
+    while (1) {
+#line 6 "examples/fibonacci.tpp"
+        if (n__g == 10) {
+#line 7 "examples/fibonacci.tpp"
+            break;
+#line 6 "examples/fibonacci.tpp"
+        }
+#line 10 "examples/fibonacci.tpp"
+        printf("%lld\n", (long long)(b__g));
+#line 12 "examples/fibonacci.tpp"
+        long long c__l = a__g + b__g;
+#line 13 "examples/fibonacci.tpp"
+        a__g = b__g;
+#line 14 "examples/fibonacci.tpp"
+        b__g = c__l;
+#line 15 "examples/fibonacci.tpp"
+        n__g = n__g + 1;
+#line 5 "examples/fibonacci.tpp"
+    }
+#line 5 "examples/fibonacci.tpp"
+    return 0;
+#line 5 "examples/fibonacci.tpp"
+}
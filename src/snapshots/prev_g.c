@@ -0,0 +1,26 @@
+#include <stdbool.h>
+#include <stdio.h>
+
+#line 1 "examples/prev.tpp"
+long long x__g = 0; long long x__g__prev = 0;
+
+#line 1 "examples/prev.tpp"
+int main() {
+#line 1 "examples/prev.tpp"
+    x__g = 1; x__g__prev = x__g;
+#line 2 "examples/prev.tpp"
+    { long long x__g__next = 2; x__g__prev = x__g; x__g = x__g__next; }
+#line 3 "examples/prev.tpp"
+    { long long x__g__next = 3; x__g__prev = x__g; x__g = x__g__next; }
+#line 5 "examples/prev.tpp"
+    printf("%lld\n", (long long)(x__g__prev));
+#line 7 "examples/prev.tpp"
+    { long long x__g__next = x__g__prev; x__g__prev = x__g; x__g = x__g__next; }
+#line 8 "examples/prev.tpp"
+    printf("%lld\n", (long long)(x__g));
+#line 9 "examples/prev.tpp"
+    printf("%lld\n", (long long)(x__g__prev));
+#line 9 "examples/prev.tpp"
+    return 0;
+#line 9 "examples/prev.tpp"
+}
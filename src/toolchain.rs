@@ -0,0 +1,73 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// A directory of our own for intermediate files. It is removed again when
+// dropped, unless the files should be kept
+pub struct TempDir {
+    path: PathBuf,
+    keep: bool,
+}
+
+impl TempDir {
+    pub fn new(keep: bool) -> std::io::Result<Self> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.subsec_nanos())
+            .unwrap_or_default();
+        let path = std::env::temp_dir().join(format!("trunkpp-{}-{}", std::process::id(), nanos));
+
+        // Creating fails when the directory already exists, so nobody else
+        // can have put files in it, and nobody else can read it
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder.create(&path)?;
+
+        Ok(TempDir { path, keep })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        if self.keep {
+            eprintln!("note: kept temporary files in {}", self.path.display());
+        } else {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+}
+
+// Compiles a C or assembly file and links it into a binary with the C
+// compiler driver. Whatever it reports is passed on, and for C code it
+// reports trunk++ lines thanks to the #line directives. Those end up in the
+// debug information as well
+pub fn build(cc: &str, source: &Path, binary: &Path, debug: bool) -> Result<(), String> {
+    let mut command = Command::new(cc);
+    if debug {
+        command.arg("-g");
+    }
+
+    let output = command
+        .arg(source)
+        .arg("-o")
+        .arg(binary)
+        .output()
+        .map_err(|err| format!("could not run the C compiler `{}`: {}", cc, err))?;
+
+    eprint!("{}", String::from_utf8_lossy(&output.stderr));
+
+    if !output.status.success() {
+        return Err(format!(
+            "the C compiler `{}` failed ({})",
+            cc, output.status
+        ));
+    }
+
+    Ok(())
+}
@@ -1,44 +1,539 @@
-use crate::ast::{Ast, BinaryOperator, Expression, Statement};
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
 
-fn template(code: &str) -> String {
-    let head = "#include <stdlib.h>\n#include <stdio.h>\nint main(){\n";
-    let tail = "return 0;\n}";
+use crate::ast::{
+    Ast, BinaryOperator, Expression, ExpressionKind, Statement, StatementKind, Type, UnaryOperator,
+};
+use crate::semantic::Analysis;
+use crate::writer::CodeWriter;
 
-    let mut result: String = head.to_string();
-    result.push_str(code);
-    result.push_str(tail);
+const PRINT_BOOL_HELPER: &str = "static void print_bool__rt(bool value) {
+    printf(\"%s\\n\", value ? \"true\" : \"false\");
+}
+";
+
+// Support routines for arrays of one element type. ARRAY is replaced by the
+// name of the array type and ELEMENT by the C type of its elements. Arrays
+// live on the heap and are never freed, so they can be returned from
+// functions. Every access goes through ARRAY__at, which stops the program
+// when the index is out of bounds
+const ARRAY_HELPERS: &str = "static inline ARRAY__t ARRAY__new(long long length, ELEMENT *values) {
+    ARRAY__t array = { malloc(sizeof(ELEMENT) * length), length };
+    memcpy(array.data, values, sizeof(ELEMENT) * length);
+    return array;
+}
+static inline ELEMENT *ARRAY__at(ARRAY__t array, long long index, int line) {
+    if (index < 0 || index >= array.length) {
+        fflush(stdout);
+        fprintf(stderr, \"error: index %lld is out of bounds for an array of length %lld on line %d\\n\", index, array.length, line);
+        exit(1);
+    }
+    return &array.data[index];
+}
+";
+
+// Reads a line of any length from standard input, without the line break.
+// `end` is set when standard input ended before anything could be read
+const READ_LINE_HELPER: &str = "static inline char *read_line__rt(bool *end) {
+    size_t length = 0, capacity = 64;
+    char *line = malloc(capacity);
+    int c;
+    while ((c = getchar()) != EOF && c != '\\n') {
+        if (length + 1 == capacity) {
+            capacity *= 2;
+            line = realloc(line, capacity);
+        }
+        line[length++] = c;
+    }
+    if (length > 0 && line[length - 1] == '\\r') {
+        length--;
+    }
+    line[length] = '\\0';
+    *end = c == EOF && length == 0;
+    return line;
+}
+";
+
+// `input()` gives an empty string once standard input has ended
+const INPUT_HELPER: &str = "static inline const char *input__rt(void) {
+    bool end;
+    return read_line__rt(&end);
+}
+";
+
+// `read_int()` stops the program when the line is not an integer or there is
+// nothing left to read
+const READ_INT_HELPER: &str = "static inline long long read_int__rt(int line) {
+    bool end;
+    char *text = read_line__rt(&end);
+    if (end) {
+        fflush(stdout);
+        fprintf(stderr, \"error: expected an integer but standard input ended on line %d\\n\", line);
+        exit(1);
+    }
+    char *rest;
+    errno = 0;
+    long long value = strtoll(text, &rest, 10);
+    while (isspace((unsigned char)*rest)) {
+        rest++;
+    }
+    if (rest == text || *rest != '\\0' || errno == ERANGE) {
+        fflush(stdout);
+        fprintf(stderr, \"error: expected an integer but read \\\"%s\\\" on line %d\\n\", text, line);
+        exit(1);
+    }
+    free(text);
+    return value;
+}
+";
+
+// The generated C file, kept in sections that are only joined together at
+// the end, so that code for any part of the file can be added at any time
+#[derive(Default)]
+struct TranslationUnit {
+    includes: Vec<&'static str>,
+    typedefs: Vec<String>,
+    // Runtime support routines by name, so each one is only emitted once
+    helpers: Vec<(String, String)>,
+    prototypes: Vec<String>,
+    globals: Vec<String>,
+    functions: Vec<String>,
+    main: String,
+}
+
+impl TranslationUnit {
+    fn new() -> Self {
+        let mut unit = Self::default();
+        unit.include("stdbool.h");
+        unit.include("stdio.h");
+        unit
+    }
 
-    result
+    fn include(&mut self, header: &'static str) {
+        if !self.includes.contains(&header) {
+            self.includes.push(header);
+        }
+    }
+
+    // Adds a routine the generated code calls into and returns whether it
+    // was new. Helpers come before the user functions so that those can call
+    // them without a prototype
+    fn helper(&mut self, name: &str, code: &str) -> bool {
+        if self.helpers.iter().any(|(helper, _)| helper == name) {
+            return false;
+        }
+
+        self.helpers.push((name.to_string(), code.to_string()));
+        true
+    }
+
+    fn render(&self) -> String {
+        let includes: Vec<String> = self
+            .includes
+            .iter()
+            .map(|header| format!("#include <{}>\n", header))
+            .collect();
+        let helpers: Vec<String> = self.helpers.iter().map(|(_, code)| code.clone()).collect();
+
+        // Sections are separated by an empty line, empty ones are left out
+        [
+            includes.concat(),
+            self.typedefs.concat(),
+            helpers.concat(),
+            self.prototypes.concat(),
+            self.globals.concat(),
+            self.functions.concat(),
+            self.main.clone(),
+        ]
+        .into_iter()
+        .filter(|section| !section.is_empty())
+        .collect::<Vec<String>>()
+        .join("\n")
+    }
 }
 
-pub struct Transpiler {}
+pub struct Transpiler {
+    // Variables that need a shadow copy holding their previous value
+    history: HashSet<String>,
+    // Labels of the loops enclosing the statement being compiled, with the
+    // unique name used for their C labels
+    loop_labels: RefCell<Vec<(String, String)>>,
+    label_count: Cell<usize>,
+    // Top level variables declared so far. They are C globals, which get a
+    // suffix so that they can't clash with the C library or with main
+    globals: RefCell<HashSet<String>>,
+    // Parameters of the function being compiled, they shadow globals
+    parameters: RefCell<Vec<String>>,
+    unit: RefCell<TranslationUnit>,
+    // C string literal with the path of the trunk++ file, when the C code
+    // gets #line directives so that the C compiler reports trunk++ lines
+    source: Option<String>,
+}
 
 impl Transpiler {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(analysis: Analysis) -> Self {
+        Self {
+            history: analysis.history,
+            loop_labels: RefCell::new(Vec::new()),
+            label_count: Cell::new(0),
+            globals: RefCell::new(HashSet::new()),
+            parameters: RefCell::new(Vec::new()),
+            unit: RefCell::new(TranslationUnit::new()),
+            source: None,
+        }
+    }
+
+    // Puts a #line directive pointing into the trunk++ file before every
+    // statement and function
+    pub fn line_directives(mut self, file_path: &str) -> Self {
+        self.source = Some(Self::compile_string(file_path));
+        self
     }
 
     pub fn transpile(&self, ast: Ast) -> String {
-        let mut code = String::new();
+        let mut main = CodeWriter::new();
+        // main runs the top level of the program, which starts at line 1
+        if let Some(source) = &self.source {
+            main.directive(&format!("#line 1 {}", source));
+        }
+
+        main.block("int main()", |main| {
+            for statement in ast.statements.iter() {
+                match &statement.kind {
+                    StatementKind::FunctionDeclaration(
+                        name,
+                        parameters,
+                        return_type,
+                        statements,
+                    ) => {
+                        let signature = self.function_signature(name, parameters, return_type);
+
+                        let mut function = CodeWriter::new();
+                        self.line_directive(&mut function, statement);
+                        *self.parameters.borrow_mut() = parameters
+                            .iter()
+                            .map(|(name, _)| name.to_string())
+                            .collect();
+                        function.block(&signature, |function| {
+                            self.compile_function_body(function, statement, parameters, statements);
+                            self.line_directive(function, statement);
+                        });
+                        self.parameters.borrow_mut().clear();
+
+                        // The prototype and the global below point at the
+                        // declaration too, so that C errors in them do as well
+                        let mut prototype = CodeWriter::new();
+                        self.line_directive(&mut prototype, statement);
+                        prototype.line(&format!("{};", signature));
+
+                        let mut unit = self.unit.borrow_mut();
+                        unit.prototypes.push(prototype.finish());
+                        unit.functions.push(function.finish());
+                    }
+                    // Top level variables live at file scope so that functions
+                    // can use them. They start out zeroed and get their initial
+                    // value when main reaches the declaration
+                    StatementKind::VariableDeclaration(name, value) => {
+                        let ty = value.ty();
+                        let c_type = self.c_type(ty);
+                        let value = self.compile_expression(value);
+
+                        self.globals.borrow_mut().insert(name.to_string());
+                        let variable = self.variable_name(name);
+                        let mut names = vec![variable.clone()];
+                        if self.history.contains(name) {
+                            names.push(Self::previous_name(&variable));
+                        }
+
+                        let mut initialization = format!("{} = {};", variable, value);
+                        if self.history.contains(name) {
+                            initialization +=
+                                &format!(" {} = {};", Self::previous_name(&variable), variable);
+                        }
+                        self.line_directive(main, statement);
+                        main.line(&initialization);
 
-        for statement in ast.statements {
-            code.push_str(&Self::compile_statement(&statement));
+                        let declarations: Vec<String> = names
+                            .iter()
+                            .map(|name| format!("{} {} = {};", c_type, name, Self::zero_value(ty)))
+                            .collect();
+                        let mut global = CodeWriter::new();
+                        self.line_directive(&mut global, statement);
+                        global.line(&declarations.join(" "));
+                        self.unit.borrow_mut().globals.push(global.finish());
+                    }
+                    _ => self.compile_statement(main, statement),
+                }
+            }
+            // The end of main belongs to the last statement of the program
+            let last = ast.statements.last();
+            if let Some(last) = last {
+                self.line_directive(main, last);
+            }
+            main.line("return 0;");
+            if let Some(last) = last {
+                self.line_directive(main, last);
+            }
+        });
+
+        let mut unit = self.unit.replace(TranslationUnit::new());
+        unit.main = main.finish();
+        unit.render()
+    }
+
+    // Functions get a suffix so that they can't clash with the C library or
+    // with main
+    fn function_name(name: &str) -> String {
+        format!("{}__fn", name)
+    }
+
+    fn function_signature(
+        &self,
+        name: &str,
+        parameters: &[(String, Type)],
+        return_type: &Option<Type>,
+    ) -> String {
+        let parameters: Vec<String> = parameters
+            .iter()
+            .map(|(parameter, ty)| format!("{} {}", self.c_type(ty), Self::local_name(parameter)))
+            .collect();
+
+        format!(
+            "{} {}({})",
+            return_type
+                .as_ref()
+                .map_or("void".to_string(), |ty| self.c_type(ty)),
+            Self::function_name(name),
+            match parameters.is_empty() {
+                true => "void".to_string(),
+                false => parameters.join(", "),
+            }
+        )
+    }
+
+    fn compile_function_body(
+        &self,
+        writer: &mut CodeWriter,
+        declaration: &Statement,
+        parameters: &[(String, Type)],
+        statements: &[Statement],
+    ) {
+        // Parameters start out with their argument as previous value
+        let copies: Vec<String> = parameters
+            .iter()
+            .filter(|(parameter, _)| self.history.contains(parameter))
+            .map(|(parameter, ty)| {
+                let parameter = Self::local_name(parameter);
+                format!(
+                    "{} {} = {};",
+                    self.c_type(ty),
+                    Self::previous_name(&parameter),
+                    parameter
+                )
+            })
+            .collect();
+        if !copies.is_empty() {
+            self.line_directive(writer, declaration);
+            writer.line(&copies.join(" "));
+        }
+
+        self.compile_block(writer, statements);
+    }
+
+    fn c_type(&self, ty: &Type) -> String {
+        match ty {
+            Type::Int => "long long".to_string(),
+            Type::Bool => "bool".to_string(),
+            Type::String => "const char *".to_string(),
+            Type::Array(element) => format!("{}__t", self.array_type(element)),
         }
+    }
 
-        template(&code)
+    fn zero_value(ty: &Type) -> &'static str {
+        match ty {
+            Type::Int => "0",
+            Type::Bool => "false",
+            Type::String => "\"\"",
+            Type::Array(_) => "{0}",
+        }
+    }
+
+    // Name used for a type in the names of generated C types and helpers
+    fn type_name(ty: &Type) -> String {
+        match ty {
+            Type::Int => "int".to_string(),
+            Type::Bool => "bool".to_string(),
+            Type::String => "string".to_string(),
+            Type::Array(element) => format!("{}_array", Self::type_name(element)),
+        }
+    }
+
+    // Declares the C struct and helpers for arrays of the given element type
+    // the first time they are used, and returns the name of the array type
+    fn array_type(&self, element: &Type) -> String {
+        let name = Self::type_name(&Type::Array(Box::new(element.clone())));
+        let element = self.c_type(element);
+
+        let mut unit = self.unit.borrow_mut();
+        let helpers = ARRAY_HELPERS
+            .replace("ARRAY", &name)
+            .replace("ELEMENT", &element);
+        if unit.helper(&name, &helpers) {
+            unit.include("stdlib.h");
+            unit.include("string.h");
+            unit.typedefs.push(format!(
+                "typedef struct {{ {} *data; long long length; }} {}__t;\n",
+                element, name
+            ));
+        }
+
+        name
+    }
+
+    fn read_line_helper(&self) {
+        let mut unit = self.unit.borrow_mut();
+        unit.include("stdlib.h");
+        unit.helper("read_line__rt", READ_LINE_HELPER);
+    }
+
+    // Pointer to an array element, checked against the bounds of the array
+    fn compile_element(&self, element: &Type, array: &str, index: &str, line: usize) -> String {
+        format!(
+            "(*{}__at({}, {}, {}))",
+            self.array_type(element),
+            array,
+            index,
+            line
+        )
+    }
+
+    // Name of the shadow variable that holds the previous value of a variable
+    fn previous_name(name: &str) -> String {
+        format!("{}__prev", name)
+    }
+
+    // The C name of a local variable, parameter or loop variable. Every user
+    // name gets a suffix, so it can't clash with C keywords or libc names
+    // like `int` or `printf`, nor with the names made up here
+    fn local_name(name: &str) -> String {
+        format!("{}__l", name)
+    }
+
+    // The C name of a variable, with the suffix if it is a global
+    fn variable_name(&self, name: &str) -> String {
+        let global = self.globals.borrow().contains(name)
+            && !self
+                .parameters
+                .borrow()
+                .iter()
+                .any(|parameter| parameter == name);
+
+        match global {
+            true => format!("{}__g", name),
+            false => Self::local_name(name),
+        }
     }
 
-    fn compile_expression(expression: &Expression) -> String {
-        match expression {
-            Expression::Integer(value) => value.to_string(),
-            Expression::Variable(name) => name.to_string(),
-            Expression::ParenthesisExpression(inner) => {
-                format!("({})", Self::compile_expression(inner))
+    // Turns a string back into a C string literal
+    fn compile_string(value: &str) -> String {
+        let mut literal = String::from("\"");
+        for c in value.chars() {
+            match c {
+                '"' => literal.push_str("\\\""),
+                '\\' => literal.push_str("\\\\"),
+                '\n' => literal.push_str("\\n"),
+                '\t' => literal.push_str("\\t"),
+                c if c.is_control() => literal.push_str(&format!("\\{:03o}", c as u32)),
+                c => literal.push(c),
             }
-            Expression::BinaryOperation(left, operation, right) => {
+        }
+        literal.push('"');
+
+        literal
+    }
+
+    fn compile_expression(&self, expression: &Expression) -> String {
+        match &expression.kind {
+            ExpressionKind::Integer(value) => value.to_string(),
+            ExpressionKind::Bool(value) => value.to_string(),
+            ExpressionKind::String(value) => Self::compile_string(value),
+            ExpressionKind::Variable(name) => self.variable_name(name),
+            ExpressionKind::Previous(name) => Self::previous_name(&self.variable_name(name)),
+            ExpressionKind::UnaryOperation(operator, operand) => format!(
+                "{}{}",
+                match operator {
+                    UnaryOperator::Not => "!",
+                },
+                self.compile_expression(operand)
+            ),
+            ExpressionKind::ParenthesisExpression(inner) => {
+                format!("({})", self.compile_expression(inner))
+            }
+            ExpressionKind::ArrayLiteral(elements) => {
+                let Type::Array(element) = expression.ty() else {
+                    unreachable!("array literals are arrays");
+                };
+                let values: Vec<String> = elements
+                    .iter()
+                    .map(|element| self.compile_expression(element))
+                    .collect();
+
+                format!(
+                    "{}__new({}, ({}[]){{{}}})",
+                    self.array_type(element),
+                    elements.len(),
+                    self.c_type(element),
+                    values.join(", ")
+                )
+            }
+            ExpressionKind::Index(array, index) => self.compile_element(
+                expression.ty(),
+                &self.compile_expression(array),
+                &self.compile_expression(index),
+                expression.span.line,
+            ),
+            ExpressionKind::Length(array) => {
+                format!("{}.length", self.compile_expression(array))
+            }
+            ExpressionKind::Input => {
+                self.read_line_helper();
+                self.unit.borrow_mut().helper("input__rt", INPUT_HELPER);
+                "input__rt()".to_string()
+            }
+            ExpressionKind::ReadInt => {
+                self.read_line_helper();
+                let mut unit = self.unit.borrow_mut();
+                unit.include("ctype.h");
+                unit.include("errno.h");
+                unit.helper("read_int__rt", READ_INT_HELPER);
+                format!("read_int__rt({})", expression.span.line)
+            }
+            ExpressionKind::Call(name, arguments) => {
+                let arguments: Vec<String> = arguments
+                    .iter()
+                    .map(|argument| self.compile_expression(argument))
+                    .collect();
+                format!("{}({})", Self::function_name(name), arguments.join(", "))
+            }
+            // Strings are pointers in C, so they have to be compared by content
+            ExpressionKind::BinaryOperation(left, operation, right)
+                if *left.ty() == Type::String =>
+            {
+                self.unit.borrow_mut().include("string.h");
+                format!(
+                    "strcmp({}, {}) {} 0",
+                    self.compile_expression(left),
+                    self.compile_expression(right),
+                    match operation {
+                        BinaryOperator::NotEqual => "!=",
+                        _ => "==",
+                    }
+                )
+            }
+            ExpressionKind::BinaryOperation(left, operation, right) => {
                 format!(
                     "{} {} {}",
-                    Self::compile_expression(left),
+                    self.compile_expression(left),
                     match operation {
                         BinaryOperator::Add => "+",
                         BinaryOperator::Subtract => "-",
@@ -46,73 +541,365 @@ impl Transpiler {
                         BinaryOperator::Divide => "/",
                         BinaryOperator::Equal => "==",
                         BinaryOperator::NotEqual => "!=",
+                        BinaryOperator::Less => "<",
+                        BinaryOperator::LessEqual => "<=",
+                        BinaryOperator::Greater => ">",
+                        BinaryOperator::GreaterEqual => ">=",
+                        BinaryOperator::And => "&&",
+                        BinaryOperator::Or => "||",
                     },
-                    Self::compile_expression(right)
+                    self.compile_expression(right)
                 )
             }
         }
     }
 
-    fn compile_statement(statement: &Statement) -> String {
-        let mut code = String::new();
+    // Points the next C line at the line of the statement. Every statement is
+    // compiled to a single C line, and closing braces get a directive of
+    // their own, so no C line runs on into the trunk++ lines after it
+    fn line_directive(&self, writer: &mut CodeWriter, statement: &Statement) {
+        if let Some(source) = &self.source {
+            writer.directive(&format!("#line {} {}", statement.span.line, source));
+        }
+    }
 
-        match statement {
-            Statement::VariableDeclaration(name, value) => {
-                let var_type = "int"; // only integers supported atm
-                code.push_str(
-                    format!(
-                        "{} {} = {};\n",
-                        var_type,
-                        name,
-                        Self::compile_expression(value)
-                    )
-                    .as_str(),
+    fn compile_block(&self, writer: &mut CodeWriter, statements: &[Statement]) {
+        for statement in statements.iter() {
+            self.compile_statement(writer, statement);
+        }
+    }
+
+    fn compile_statement(&self, writer: &mut CodeWriter, statement: &Statement) {
+        self.line_directive(writer, statement);
+
+        match &statement.kind {
+            StatementKind::VariableDeclaration(name, value) => {
+                let c_type = self.c_type(value.ty());
+                let variable = Self::local_name(name);
+                let mut declaration = format!(
+                    "{} {} = {};",
+                    c_type,
+                    variable,
+                    self.compile_expression(value)
                 );
+
+                // Until the first assignment the previous value is the initial one
+                if self.history.contains(name) {
+                    declaration += &format!(
+                        " {} {} = {};",
+                        c_type,
+                        Self::previous_name(&variable),
+                        variable
+                    );
+                }
+                writer.line(&declaration);
             }
-            Statement::Assignment(name, expression) => {
-                code.push_str(&format!(
-                    "{} = {};\n",
+            // The new value is computed before the old one is saved, so that
+            // `x = prev x!` swaps the current and previous value
+            StatementKind::Assignment(name, expression) if self.history.contains(name) => {
+                let c_type = self.c_type(expression.ty());
+                let value = self.compile_expression(expression);
+                let name = self.variable_name(name);
+                writer.line(&format!(
+                    "{{ {} {}__next = {}; {} = {}; {} = {}__next; }}",
+                    c_type,
+                    name,
+                    value,
+                    Self::previous_name(&name),
+                    name,
                     name,
-                    Self::compile_expression(expression)
+                    name
                 ));
             }
-            Statement::LoopStatement(statements) => {
-                let mut new_code = String::new();
-                new_code += "while(1){\n";
+            // The element type of each array along the way is the type of the
+            // value wrapped in one array for every index still to come
+            StatementKind::IndexAssignment(name, indices, expression) => {
+                let mut target = self.variable_name(name);
+                for (i, index) in indices.iter().enumerate() {
+                    let mut element = expression.ty().clone();
+                    for _ in i + 1..indices.len() {
+                        element = Type::Array(Box::new(element));
+                    }
 
-                for statement in statements.iter() {
-                    new_code += &Self::compile_statement(statement);
+                    target = self.compile_element(
+                        &element,
+                        &target,
+                        &self.compile_expression(index),
+                        statement.span.line,
+                    );
                 }
 
-                new_code += "}\n";
-
-                code.push_str(&new_code);
+                writer.line(&format!(
+                    "{} = {};",
+                    target,
+                    self.compile_expression(expression)
+                ));
             }
-            Statement::IfStatement(condition, statements) => {
-                let mut new_code = String::new();
-                new_code += "if(";
-                new_code += &Self::compile_expression(condition);
-                new_code += "){\n";
+            StatementKind::Assignment(name, expression) => {
+                writer.line(&format!(
+                    "{} = {};",
+                    self.variable_name(name),
+                    self.compile_expression(expression)
+                ));
+            }
+            StatementKind::LoopStatement(..)
+            | StatementKind::WhileStatement(..)
+            | StatementKind::ForStatement(..) => self.compile_loop(writer, statement, None),
+            StatementKind::LabeledStatement(label, statement) => {
+                self.compile_loop(writer, statement, Some(label))
+            }
+            StatementKind::IfStatement(condition, statements, else_statements) => {
+                writer.line(&format!("if ({}) {{", self.compile_expression(condition)));
+                writer.indent();
+                self.compile_block(writer, statements);
+                writer.dedent();
 
-                for statement in statements.iter() {
-                    new_code += &Self::compile_statement(statement);
+                // Else branches that only hold an if become an `else if` chain
+                let mut else_statements = else_statements.as_deref();
+                while let Some(
+                    [else_if @ Statement {
+                        kind: StatementKind::IfStatement(condition, statements, next),
+                        ..
+                    }],
+                ) = else_statements
+                {
+                    self.line_directive(writer, else_if);
+                    writer.line(&format!(
+                        "}} else if ({}) {{",
+                        self.compile_expression(condition)
+                    ));
+                    writer.indent();
+                    self.compile_block(writer, statements);
+                    writer.dedent();
+                    else_statements = next.as_deref();
                 }
 
-                new_code += "}\n";
-
-                code.push_str(&new_code);
+                if let Some(else_statements) = else_statements {
+                    self.line_directive(writer, statement);
+                    writer.line("} else {");
+                    writer.indent();
+                    self.compile_block(writer, else_statements);
+                    writer.dedent();
+                }
+                self.line_directive(writer, statement);
+                writer.line("}");
             }
-            Statement::PrintStatement(expression) => {
-                code.push_str(&format!(
-                    "printf(\"%d\\n\", {});\n",
-                    Self::compile_expression(expression)
-                ));
+            StatementKind::PrintStatement(expression) => {
+                let value = self.compile_expression(expression);
+                writer.line(&match expression.ty() {
+                    // Integer literals are plain ints in C
+                    Type::Int => format!("printf(\"%lld\\n\", (long long)({}));", value),
+                    Type::Bool => {
+                        self.unit
+                            .borrow_mut()
+                            .helper("print_bool__rt", PRINT_BOOL_HELPER);
+                        format!("print_bool__rt({});", value)
+                    }
+                    Type::String => format!("printf(\"%s\\n\", {});", value),
+                    Type::Array(_) => unreachable!("the type checker rejects printing arrays"),
+                });
+            }
+            StatementKind::ExpressionStatement(expression) => {
+                writer.line(&format!("{};", self.compile_expression(expression)));
+            }
+            StatementKind::ReturnStatement(Some(expression)) => {
+                writer.line(&format!("return {};", self.compile_expression(expression)));
+            }
+            StatementKind::ReturnStatement(None) => writer.line("return;"),
+            StatementKind::FunctionDeclaration(..) => {
+                unreachable!("functions are only declared at the top level")
+            }
+            StatementKind::BreakStatement(None) => writer.line("break;"),
+            StatementKind::ContinueStatement(None) => writer.line("continue;"),
+            // C has no labeled break or continue, so they jump to the labels
+            // placed by compile_loop instead
+            StatementKind::BreakStatement(Some(label)) => {
+                writer.line(&format!("goto {}__break;", self.c_label(label)));
             }
-            Statement::BreakStatement => {
-                code.push_str("break;\n");
+            StatementKind::ContinueStatement(Some(label)) => {
+                writer.line(&format!("goto {}__continue;", self.c_label(label)));
             }
         }
+    }
+
+    // C labels are shared by the whole function, so loops that reuse a label
+    // get a number to keep them apart
+    fn c_label(&self, label: &str) -> String {
+        self.loop_labels
+            .borrow()
+            .iter()
+            .rev()
+            .find(|(name, _)| name == label)
+            .map(|(_, c_label)| c_label.to_string())
+            .expect("semantic analysis checks that labels exist")
+    }
+
+    // Compiles a loop statement. Labeled loops get a `label__continue` label at
+    // the end of their body and a `label__break` label right after the loop
+    fn compile_loop(&self, writer: &mut CodeWriter, statement: &Statement, label: Option<&str>) {
+        let label = label.map(|label| {
+            let c_label = format!("{}_{}", label, self.label_count.get());
+            self.label_count.set(self.label_count.get() + 1);
+            self.loop_labels
+                .borrow_mut()
+                .push((label.to_string(), c_label.clone()));
+            c_label
+        });
+
+        let (header, statements) = match &statement.kind {
+            StatementKind::LoopStatement(statements) => ("while (1)".to_string(), statements),
+            StatementKind::WhileStatement(condition, statements) => (
+                format!("while ({})", self.compile_expression(condition)),
+                statements,
+            ),
+            StatementKind::ForStatement(name, start, end, statements) => {
+                let variable = Self::local_name(name);
+                let mut header = format!(
+                    "for (long long {} = {}, {}__end = {}",
+                    variable,
+                    self.compile_expression(start),
+                    variable,
+                    self.compile_expression(end)
+                );
+                if self.history.contains(name) {
+                    header += &format!(
+                        ", {} = {}; {} < {}__end; {} = {}, {}++)",
+                        Self::previous_name(&variable),
+                        variable,
+                        variable,
+                        variable,
+                        Self::previous_name(&variable),
+                        variable,
+                        variable
+                    );
+                } else {
+                    header += &format!("; {} < {}__end; {}++)", variable, variable, variable);
+                }
+                (header, statements)
+            }
+            _ => unreachable!("only loops can be labeled"),
+        };
+
+        writer.block(&header, |writer| {
+            self.compile_block(writer, statements);
+            self.line_directive(writer, statement);
+            if let Some(label) = &label {
+                writer.line(&format!("{}__continue:;", label));
+            }
+        });
+
+        if let Some(label) = &label {
+            writer.line(&format!("{}__break:;", label));
+            self.loop_labels.borrow_mut().pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::AstParser;
+    use crate::lexer::Lexer;
+    use crate::semantic::Semantic;
+    use crate::typechecker::TypeChecker;
+    use std::fs;
+
+    fn transpile(file_path: &str, line_directives: bool) -> String {
+        let source = fs::read_to_string(file_path).expect("the example exists");
+        transpile_source(source, file_path, line_directives)
+    }
+
+    fn transpile_source(source: String, file_path: &str, line_directives: bool) -> String {
+        let tokens = Lexer::new(source).tokenize().expect("the example lexes");
+        let (mut ast, errors) = AstParser::new(tokens).parse();
+        assert!(errors.is_empty(), "syntax errors in {}", file_path);
+
+        let analysis = Semantic::new(ast.clone())
+            .check()
+            .unwrap_or_else(|_| panic!("semantic errors in {}", file_path));
+        assert!(TypeChecker::new().check(&mut ast).is_ok());
+
+        let mut transpiler = Transpiler::new(analysis);
+        if line_directives {
+            transpiler = transpiler.line_directives(file_path);
+        }
+        transpiler.transpile(ast)
+    }
+
+    // Compares the output with the snapshot file. Running the tests with
+    // UPDATE_SNAPSHOTS=1 writes the output to it instead
+    fn assert_snapshot(output: &str, snapshot: &str) {
+        let path = format!("src/snapshots/{}", snapshot);
+        if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            fs::write(&path, output).expect("the snapshot can be written");
+            return;
+        }
+
+        let expected = fs::read_to_string(&path).expect("the snapshot exists");
+        assert!(
+            output == expected,
+            "{} is out of date, rerun with UPDATE_SNAPSHOTS=1 if the change is intended\n{}",
+            path,
+            output
+        );
+    }
+
+    #[test]
+    fn fibonacci_snapshot() {
+        let output = transpile("examples/fibonacci.tpp", false);
+        assert_snapshot(&output, "fibonacci.c");
+    }
+
+    #[test]
+    fn fibonacci_line_directives_snapshot() {
+        let output = transpile("examples/fibonacci.tpp", true);
+        assert_snapshot(&output, "fibonacci_g.c");
+    }
+
+    #[test]
+    fn prev_line_directives_snapshot() {
+        let output = transpile("examples/prev.tpp", true);
+        assert_snapshot(&output, "prev_g.c");
+    }
+
+    #[test]
+    fn output_is_stable() {
+        for file_path in ["examples/arrays.tpp", "examples/functions.tpp"] {
+            assert_eq!(transpile(file_path, true), transpile(file_path, true));
+        }
+    }
+
+    // User names that are also C keywords, libc names or look like the names
+    // the transpiler makes up
+    const C_NAMES: &str = "var EOF = 1!
+fn f(double) {
+    return double * 2!
+}
+loop {
+    var printf = 5!
+    print printf!
+    break!
+}
+if (true) {
+    var int = f(3)!
+    int = int + EOF!
+    print prev int!
+}
+for errno in 0..2 {
+    var strcmp = errno!
+    var bool = strcmp == 1!
+    print bool!
+}
+var x__g = 4!
+fn g(x__l) {
+    return x__l + x__g!
+}
+print g(1)!
+";
 
-        code
+    #[test]
+    fn user_names_do_not_clash_with_c_names() {
+        let output = transpile_source(C_NAMES.to_string(), "c_names.tpp", false);
+        assert_snapshot(&output, "c_names.c");
     }
 }
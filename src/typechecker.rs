@@ -0,0 +1,559 @@
+use core::fmt;
+use std::collections::HashMap;
+
+use crate::ast::{
+    Ast, BinaryOperator, Expression, ExpressionKind, Statement, StatementKind, Type, UnaryOperator,
+};
+use crate::diagnostics::Diagnostic;
+use crate::lexer::Span;
+
+#[derive(Debug)]
+pub enum TypeErrorKind {
+    // Operator and the types of its left and right operand
+    InvalidOperands(BinaryOperator, Type, Type),
+    InvalidOperand(UnaryOperator, Type),
+    NonBooleanCondition(Type),
+    NonIntegerRange(Type),
+    // Variable name, declared type and the type of the assigned value
+    MismatchedAssignment(String, Type, Type),
+    // Function, parameter, parameter type and the type of the argument
+    MismatchedArgument(String, String, Type, Type),
+    // Return type of the function and the type of the returned value
+    MismatchedReturn(Type, Type),
+    MissingReturnValue(Type),
+    // Function that returns nothing but whose result is used
+    VoidValue(String),
+    EmptyArray,
+    // Type of the array elements and of the offending value
+    MismatchedElement(Type, Type),
+    ExpectedArray(Type),
+    NonIntegerIndex(Type),
+    PrintArray(Type),
+}
+
+#[derive(Debug)]
+pub struct TypeError {
+    pub kind: TypeErrorKind,
+    pub span: Span,
+}
+
+impl fmt::Display for TypeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TypeErrorKind::InvalidOperands(operator, left, right) => {
+                write!(f, "Cannot use {} on {} and {}", operator, left, right)
+            }
+            TypeErrorKind::InvalidOperand(operator, operand) => {
+                write!(f, "Cannot use {} on {}", operator, operand)
+            }
+            TypeErrorKind::NonBooleanCondition(ty) => write!(f, "Condition is {}, not bool", ty),
+            TypeErrorKind::NonIntegerRange(ty) => write!(f, "Range bound is {}, not int", ty),
+            TypeErrorKind::MismatchedAssignment(name, expected, found) => write!(
+                f,
+                "Cannot assign {} to {} which is {}",
+                found, name, expected
+            ),
+            TypeErrorKind::MismatchedArgument(function, parameter, expected, found) => write!(
+                f,
+                "Cannot pass {} as {} of {} which is {}",
+                found, parameter, function, expected
+            ),
+            TypeErrorKind::MismatchedReturn(expected, found) => {
+                write!(
+                    f,
+                    "Cannot return {} from function returning {}",
+                    found, expected
+                )
+            }
+            TypeErrorKind::MissingReturnValue(expected) => {
+                write!(f, "Missing {} return value", expected)
+            }
+            TypeErrorKind::VoidValue(name) => write!(f, "Function {} returns nothing", name),
+            TypeErrorKind::EmptyArray => write!(f, "Empty array"),
+            TypeErrorKind::MismatchedElement(expected, found) => {
+                write!(f, "Array of {} cannot hold {}", expected, found)
+            }
+            TypeErrorKind::ExpectedArray(ty) => write!(f, "Expected array, found {}", ty),
+            TypeErrorKind::NonIntegerIndex(ty) => write!(f, "Index is {}, not int", ty),
+            TypeErrorKind::PrintArray(ty) => write!(f, "Cannot print {}", ty),
+        }
+    }
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}", self.kind, self.span)
+    }
+}
+
+impl TypeError {
+    pub fn diagnostic(&self) -> Diagnostic {
+        match &self.kind {
+            TypeErrorKind::InvalidOperands(operator, left, right) => Diagnostic::error(
+                "E0401",
+                format!("cannot use `{}` on {} and {}", operator, left, right),
+                self.span,
+            )
+            .with_label(format!("{} {} {}", left, operator, right))
+            .with_note(match operator {
+                BinaryOperator::Equal | BinaryOperator::NotEqual
+                    if matches!(left, Type::Array(_)) =>
+                {
+                    "arrays can't be compared, compare their elements instead"
+                }
+                BinaryOperator::Equal | BinaryOperator::NotEqual => {
+                    "both sides of a comparison have to be the same type"
+                }
+                BinaryOperator::And | BinaryOperator::Or => "logical operators only work on bools",
+                BinaryOperator::Less
+                | BinaryOperator::LessEqual
+                | BinaryOperator::Greater
+                | BinaryOperator::GreaterEqual => "only ints can be ordered",
+                _ => "arithmetic only works on ints",
+            }),
+            TypeErrorKind::InvalidOperand(operator, operand) => Diagnostic::error(
+                "E0402",
+                format!("cannot use `{}` on {}", operator, operand),
+                self.span,
+            )
+            .with_label(format!("this has type {}", operand))
+            .with_note("`!` and `not` only work on bools"),
+            TypeErrorKind::NonBooleanCondition(ty) => {
+                Diagnostic::error("E0403", "condition is not a bool", self.span)
+                    .with_label(format!("this has type {}", ty))
+                    .with_help("compare it instead, for example `if (x ;= 0) { ... }`")
+            }
+            TypeErrorKind::NonIntegerRange(ty) => {
+                Diagnostic::error("E0405", "range bound is not an int", self.span)
+                    .with_label(format!("this has type {}", ty))
+                    .with_note("for loops count from one int up to another")
+            }
+            TypeErrorKind::MismatchedAssignment(name, expected, found) => Diagnostic::error(
+                "E0404",
+                format!("cannot assign {} to `{}`", found, name),
+                self.span,
+            )
+            .with_label(format!("this has type {}", found))
+            .with_note(format!("`{}` was declared as {}", name, expected)),
+            TypeErrorKind::MismatchedArgument(function, parameter, expected, found) => {
+                Diagnostic::error(
+                    "E0406",
+                    format!("cannot pass {} as `{}` to `{}`", found, parameter, function),
+                    self.span,
+                )
+                .with_label(format!("this has type {}", found))
+                .with_note(format!("`{}` is declared as {}", parameter, expected))
+            }
+            TypeErrorKind::MismatchedReturn(expected, found) => Diagnostic::error(
+                "E0407",
+                format!(
+                    "cannot return {} from a function returning {}",
+                    found, expected
+                ),
+                self.span,
+            )
+            .with_label(format!("this has type {}", found)),
+            TypeErrorKind::MissingReturnValue(expected) => {
+                Diagnostic::error("E0408", "missing return value", self.span)
+                    .with_label(format!("expected a {} to return", expected))
+            }
+            TypeErrorKind::VoidValue(name) => Diagnostic::error(
+                "E0409",
+                format!("`{}` does not return a value", name),
+                self.span,
+            )
+            .with_label("this call has no value")
+            .with_help(format!("call it on its own with `{}(...)!`", name)),
+            TypeErrorKind::EmptyArray => Diagnostic::error("E0410", "empty array", self.span)
+                .with_label("the element type can't be inferred")
+                .with_note("arrays have a fixed size, so an empty one is of no use"),
+            TypeErrorKind::MismatchedElement(expected, found) => Diagnostic::error(
+                "E0411",
+                format!("cannot put {} in an array of {}", found, expected),
+                self.span,
+            )
+            .with_label(format!("this has type {}", found))
+            .with_note("all elements of an array have the same type"),
+            TypeErrorKind::ExpectedArray(ty) => Diagnostic::error(
+                "E0412",
+                format!("expected an array, found {}", ty),
+                self.span,
+            )
+            .with_label(format!("this has type {}", ty))
+            .with_note("only arrays can be indexed or passed to `len`"),
+            TypeErrorKind::NonIntegerIndex(ty) => {
+                Diagnostic::error("E0413", "array index is not an int", self.span)
+                    .with_label(format!("this has type {}", ty))
+            }
+            TypeErrorKind::PrintArray(ty) => {
+                Diagnostic::error("E0414", format!("cannot print {}", ty), self.span)
+                    .with_label("this is an array")
+                    .with_help("print its elements one by one in a loop")
+            }
+        }
+    }
+}
+
+// Types of the variables visible at some point in the program
+type Scope = Vec<(String, Type)>;
+
+// Parameters and return type of a function
+type Signature = (Vec<(String, Type)>, Option<Type>);
+
+// Infers the type of every expression, stores it in `Expression::ty` and
+// checks that operators, conditions and assignments are used on the right
+// types. Runs after `Semantic`, so every variable and function is known to
+// be declared and every call to have the right number of arguments
+#[derive(Default)]
+pub struct TypeChecker {
+    errors: Vec<TypeError>,
+    functions: HashMap<String, Signature>,
+    // Return type of the function being checked
+    return_type: Option<Type>,
+}
+
+impl TypeChecker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn check(mut self, ast: &mut Ast) -> Result<(), Vec<TypeError>> {
+        for statement in ast.statements.iter() {
+            if let StatementKind::FunctionDeclaration(name, parameters, return_type, _) =
+                &statement.kind
+            {
+                self.functions
+                    .insert(name.to_string(), (parameters.clone(), return_type.clone()));
+            }
+        }
+
+        self.check_statement_block(&mut ast.statements, Vec::new());
+
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    fn lookup(variables: &Scope, name: &str) -> Option<Type> {
+        variables
+            .iter()
+            .rev()
+            .find(|(variable, _)| variable == name)
+            .map(|(_, ty)| ty.clone())
+    }
+
+    // Returns None if the type could not be inferred, in which case an
+    // error has already been reported for the expression
+    fn check_expression(&mut self, expression: &mut Expression, variables: &Scope) -> Option<Type> {
+        let ty = match &mut expression.kind {
+            ExpressionKind::Integer(_) | ExpressionKind::ReadInt => Some(Type::Int),
+            ExpressionKind::Bool(_) => Some(Type::Bool),
+            ExpressionKind::String(_) | ExpressionKind::Input => Some(Type::String),
+            ExpressionKind::Variable(name) | ExpressionKind::Previous(name) => {
+                Self::lookup(variables, name)
+            }
+            ExpressionKind::ParenthesisExpression(inner) => self.check_expression(inner, variables),
+            ExpressionKind::UnaryOperation(operator, operand) => {
+                match self.check_expression(operand, variables)? {
+                    Type::Bool => Some(Type::Bool),
+                    ty => {
+                        self.errors.push(TypeError {
+                            kind: TypeErrorKind::InvalidOperand(operator.clone(), ty),
+                            span: expression.span,
+                        });
+                        None
+                    }
+                }
+            }
+            ExpressionKind::BinaryOperation(left, operator, right) => {
+                let left = self.check_expression(left, variables);
+                let right = self.check_expression(right, variables);
+                let (left, right) = (left?, right?);
+
+                let ty = match operator {
+                    BinaryOperator::Add
+                    | BinaryOperator::Subtract
+                    | BinaryOperator::Multiply
+                    | BinaryOperator::Divide => {
+                        (left == Type::Int && right == Type::Int).then_some(Type::Int)
+                    }
+                    BinaryOperator::Equal | BinaryOperator::NotEqual => {
+                        (left == right && !matches!(left, Type::Array(_))).then_some(Type::Bool)
+                    }
+                    BinaryOperator::Less
+                    | BinaryOperator::LessEqual
+                    | BinaryOperator::Greater
+                    | BinaryOperator::GreaterEqual => {
+                        (left == Type::Int && right == Type::Int).then_some(Type::Bool)
+                    }
+                    BinaryOperator::And | BinaryOperator::Or => {
+                        (left == Type::Bool && right == Type::Bool).then_some(Type::Bool)
+                    }
+                };
+
+                if ty.is_none() {
+                    self.errors.push(TypeError {
+                        kind: TypeErrorKind::InvalidOperands(operator.clone(), left, right),
+                        span: expression.span,
+                    });
+                }
+
+                ty
+            }
+            ExpressionKind::ArrayLiteral(elements) => {
+                let mut element_type = None;
+                for element in elements.iter_mut() {
+                    let Some(found) = self.check_expression(element, variables) else {
+                        continue;
+                    };
+
+                    match &element_type {
+                        None => element_type = Some(found),
+                        Some(expected) if *expected != found => self.errors.push(TypeError {
+                            kind: TypeErrorKind::MismatchedElement(expected.clone(), found),
+                            span: element.span,
+                        }),
+                        Some(_) => {}
+                    }
+                }
+
+                if elements.is_empty() {
+                    self.errors.push(TypeError {
+                        kind: TypeErrorKind::EmptyArray,
+                        span: expression.span,
+                    });
+                }
+
+                element_type.map(|element| Type::Array(Box::new(element)))
+            }
+            ExpressionKind::Index(array, index) => {
+                let element = self.check_array(array, variables);
+                self.check_index(index, variables);
+                element
+            }
+            ExpressionKind::Length(array) => {
+                self.check_array(array, variables);
+                Some(Type::Int)
+            }
+            ExpressionKind::Call(name, arguments) => {
+                let ty = self.check_call(name, arguments, variables);
+                if ty.is_none() {
+                    self.errors.push(TypeError {
+                        kind: TypeErrorKind::VoidValue(name.to_string()),
+                        span: expression.span,
+                    });
+                }
+
+                ty
+            }
+        };
+
+        expression.ty = ty.clone();
+        ty
+    }
+
+    // Returns the element type of the array
+    fn check_array(&mut self, array: &mut Expression, variables: &Scope) -> Option<Type> {
+        match self.check_expression(array, variables)? {
+            Type::Array(element) => Some(*element),
+            ty => {
+                self.errors.push(TypeError {
+                    kind: TypeErrorKind::ExpectedArray(ty),
+                    span: array.span,
+                });
+                None
+            }
+        }
+    }
+
+    fn check_index(&mut self, index: &mut Expression, variables: &Scope) {
+        match self.check_expression(index, variables) {
+            Some(Type::Int) | None => {}
+            Some(ty) => self.errors.push(TypeError {
+                kind: TypeErrorKind::NonIntegerIndex(ty),
+                span: index.span,
+            }),
+        }
+    }
+
+    // Checks the arguments against the parameters and returns the return
+    // type of the function
+    fn check_call(
+        &mut self,
+        name: &str,
+        arguments: &mut [Expression],
+        variables: &Scope,
+    ) -> Option<Type> {
+        let (parameters, return_type) = self.functions[name].clone();
+
+        for (argument, (parameter, expected)) in arguments.iter_mut().zip(parameters) {
+            match self.check_expression(argument, variables) {
+                Some(found) if found != expected => self.errors.push(TypeError {
+                    kind: TypeErrorKind::MismatchedArgument(
+                        name.to_string(),
+                        parameter,
+                        expected,
+                        found,
+                    ),
+                    span: argument.span,
+                }),
+                _ => {}
+            }
+        }
+
+        return_type
+    }
+
+    fn check_condition(&mut self, condition: &mut Expression, variables: &Scope) {
+        match self.check_expression(condition, variables) {
+            Some(Type::Bool) | None => {}
+            Some(ty) => self.errors.push(TypeError {
+                kind: TypeErrorKind::NonBooleanCondition(ty),
+                span: condition.span,
+            }),
+        }
+    }
+
+    fn check_statement_block(&mut self, statements: &mut [Statement], mut variables: Scope) {
+        for statement in statements.iter_mut() {
+            match &mut statement.kind {
+                StatementKind::IfStatement(condition, statements, else_statements) => {
+                    self.check_condition(condition, &variables);
+                    self.check_statement_block(statements, variables.clone());
+                    if let Some(else_statements) = else_statements {
+                        self.check_statement_block(else_statements, variables.clone());
+                    }
+                }
+                StatementKind::LoopStatement(statements) => {
+                    self.check_statement_block(statements, variables.clone());
+                }
+                StatementKind::WhileStatement(condition, statements) => {
+                    self.check_condition(condition, &variables);
+                    self.check_statement_block(statements, variables.clone());
+                }
+                StatementKind::ForStatement(name, start, end, statements) => {
+                    for bound in [start, end] {
+                        match self.check_expression(bound, &variables) {
+                            Some(Type::Int) | None => {}
+                            Some(ty) => self.errors.push(TypeError {
+                                kind: TypeErrorKind::NonIntegerRange(ty),
+                                span: bound.span,
+                            }),
+                        }
+                    }
+
+                    let mut body_variables = variables.clone();
+                    body_variables.push((name.to_string(), Type::Int));
+                    self.check_statement_block(statements, body_variables);
+                }
+                StatementKind::VariableDeclaration(name, value) => {
+                    if let Some(ty) = self.check_expression(value, &variables) {
+                        variables.push((name.to_string(), ty));
+                    }
+                }
+                StatementKind::Assignment(name, value) => {
+                    let found = self.check_expression(value, &variables);
+                    if let (Some(expected), Some(found)) = (Self::lookup(&variables, name), found) {
+                        if expected != found {
+                            self.errors.push(TypeError {
+                                kind: TypeErrorKind::MismatchedAssignment(
+                                    name.to_string(),
+                                    expected,
+                                    found,
+                                ),
+                                span: value.span,
+                            });
+                        }
+                    }
+                }
+                // Each index goes one array deeper, until the element that is
+                // assigned to
+                StatementKind::IndexAssignment(name, indices, value) => {
+                    let mut ty = Self::lookup(&variables, name);
+                    for index in indices.iter_mut() {
+                        ty = match ty {
+                            Some(Type::Array(element)) => Some(*element),
+                            Some(ty) => {
+                                self.errors.push(TypeError {
+                                    kind: TypeErrorKind::ExpectedArray(ty),
+                                    span: statement.span,
+                                });
+                                None
+                            }
+                            None => None,
+                        };
+                        self.check_index(index, &variables);
+                    }
+
+                    let found = self.check_expression(value, &variables);
+                    if let (Some(expected), Some(found)) = (ty, found) {
+                        if expected != found {
+                            self.errors.push(TypeError {
+                                kind: TypeErrorKind::MismatchedElement(expected, found),
+                                span: value.span,
+                            });
+                        }
+                    }
+                }
+                StatementKind::PrintStatement(value) => {
+                    if let Some(ty @ Type::Array(_)) = self.check_expression(value, &variables) {
+                        self.errors.push(TypeError {
+                            kind: TypeErrorKind::PrintArray(ty),
+                            span: value.span,
+                        });
+                    }
+                }
+                StatementKind::LabeledStatement(_, inner) => {
+                    self.check_statement_block(
+                        std::slice::from_mut(inner.as_mut()),
+                        variables.clone(),
+                    );
+                }
+                StatementKind::BreakStatement(_) | StatementKind::ContinueStatement(_) => {}
+                // Functions see the top level variables declared before them
+                // and their parameters, which are looked up first
+                StatementKind::FunctionDeclaration(_, parameters, return_type, statements) => {
+                    let mut body_variables = variables.clone();
+                    body_variables.extend(parameters.iter().cloned());
+
+                    self.return_type = return_type.clone();
+                    self.check_statement_block(statements, body_variables);
+                    self.return_type = None;
+                }
+                StatementKind::ReturnStatement(value) => {
+                    let found = match value {
+                        Some(value) => self
+                            .check_expression(value, &variables)
+                            .map(|found| (found, value.span)),
+                        None => None,
+                    };
+
+                    match (self.return_type.clone(), found) {
+                        (Some(expected), Some((found, span))) if expected != found => {
+                            self.errors.push(TypeError {
+                                kind: TypeErrorKind::MismatchedReturn(expected, found),
+                                span,
+                            })
+                        }
+                        (Some(expected), None) if value.is_none() => self.errors.push(TypeError {
+                            kind: TypeErrorKind::MissingReturnValue(expected),
+                            span: statement.span,
+                        }),
+                        _ => {}
+                    }
+                }
+                // The result of a call used as a statement may be nothing
+                StatementKind::ExpressionStatement(value) => match &mut value.kind {
+                    ExpressionKind::Call(name, arguments) => {
+                        value.ty = self.check_call(name, arguments, &variables);
+                    }
+                    _ => {
+                        self.check_expression(value, &variables);
+                    }
+                },
+            }
+        }
+    }
+}
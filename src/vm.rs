@@ -0,0 +1,176 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::bytecode::{Instruction, Program};
+use crate::interpreter::{Interpreter, RuntimeError, Value, Variable};
+
+// A running function call
+struct Frame {
+    function: usize,
+    // Index of the next instruction
+    ip: usize,
+    locals: Vec<Variable>,
+}
+
+// Runs compiled bytecode, with the same behaviour and runtime errors as the
+// interpreter. Calls live on the heap, so recursion is only limited by memory
+pub struct Vm<'a> {
+    program: &'a Program,
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+    globals: Vec<Variable>,
+}
+
+impl<'a> Vm<'a> {
+    pub fn new(program: &'a Program) -> Self {
+        Vm {
+            program,
+            stack: Vec::new(),
+            frames: Vec::new(),
+            globals: program
+                .globals
+                .iter()
+                .map(|(_, zero)| Variable::new(zero.clone()))
+                .collect(),
+        }
+    }
+
+    pub fn run(mut self) -> Result<(), RuntimeError> {
+        let program = self.program;
+        self.call(0);
+
+        while let Some(frame) = self.frames.last_mut() {
+            let function = &program.functions[frame.function];
+            let instruction = &function.code[frame.ip];
+            let span = function.spans[frame.ip];
+            frame.ip += 1;
+
+            match instruction {
+                Instruction::Int(value) => self.stack.push(Value::Int(*value)),
+                Instruction::Bool(value) => self.stack.push(Value::Bool(*value)),
+                Instruction::String(value) => self.stack.push(Value::String(value.clone())),
+                Instruction::Pop => {
+                    self.pop();
+                }
+                Instruction::Duplicate => {
+                    let value = self.stack.last().expect("the stack is empty").clone();
+                    self.stack.push(value);
+                }
+                Instruction::LoadLocal(slot) => {
+                    let value = self.frame().locals[*slot].value.clone();
+                    self.stack.push(value);
+                }
+                Instruction::LoadGlobal(slot) => {
+                    let value = self.globals[*slot].value.clone();
+                    self.stack.push(value);
+                }
+                Instruction::LoadPreviousLocal(slot) => {
+                    let value = self.frame().locals[*slot].previous.clone();
+                    self.stack.push(value);
+                }
+                Instruction::LoadPreviousGlobal(slot) => {
+                    let value = self.globals[*slot].previous.clone();
+                    self.stack.push(value);
+                }
+                Instruction::DeclareLocal(slot) => {
+                    let value = self.pop();
+                    self.frame().locals[*slot] = Variable::new(value);
+                }
+                Instruction::DeclareGlobal(slot) => {
+                    let value = self.pop();
+                    self.globals[*slot] = Variable::new(value);
+                }
+                Instruction::StoreLocal(slot) => {
+                    let value = self.pop();
+                    self.frame().locals[*slot].assign(value);
+                }
+                Instruction::StoreGlobal(slot) => {
+                    let value = self.pop();
+                    self.globals[*slot].assign(value);
+                }
+                Instruction::Binary(operator) => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    let value = Interpreter::apply(operator, left, right, span)?;
+                    self.stack.push(value);
+                }
+                Instruction::Not => {
+                    let value = self.pop().bool();
+                    self.stack.push(Value::Bool(!value));
+                }
+                Instruction::Jump(target) => self.frame().ip = *target,
+                Instruction::JumpIfFalse(target) => {
+                    if !self.pop().bool() {
+                        self.frame().ip = *target;
+                    }
+                }
+                Instruction::JumpIfTrue(target) => {
+                    if self.pop().bool() {
+                        self.frame().ip = *target;
+                    }
+                }
+                Instruction::Print => println!("{}", self.pop()),
+                Instruction::Call(function) => self.call(*function),
+                Instruction::Return => {
+                    self.frames.pop();
+                }
+                Instruction::Array(length) => {
+                    let elements = self.stack.split_off(self.stack.len() - length);
+                    self.stack
+                        .push(Value::Array(Rc::new(RefCell::new(elements))));
+                }
+                Instruction::Index => {
+                    let index = self.pop().int();
+                    let array = self.pop().array();
+                    let position = Interpreter::position(&array, index, span)?;
+                    let element = array.borrow()[position].clone();
+                    self.stack.push(element);
+                }
+                Instruction::StoreIndex => {
+                    let value = self.pop();
+                    let index = self.pop().int();
+                    let array = self.pop().array();
+                    let position = Interpreter::position(&array, index, span)?;
+                    array.borrow_mut()[position] = value;
+                }
+                Instruction::Length => {
+                    let length = self.pop().array().borrow().len();
+                    self.stack.push(Value::Int(length as i64));
+                }
+                Instruction::Input => self.stack.push(Interpreter::input()),
+                Instruction::ReadInt => {
+                    let value = Interpreter::read_int(span)?;
+                    self.stack.push(value);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("no function is running")
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("the stack is empty")
+    }
+
+    // Moves the arguments off the stack into the parameters of a new frame
+    fn call(&mut self, function: usize) {
+        let declaration = &self.program.functions[function];
+        let arguments = self
+            .stack
+            .split_off(self.stack.len() - declaration.parameters);
+
+        // Other locals get their value when their declaration runs
+        let mut locals: Vec<Variable> = arguments.into_iter().map(Variable::new).collect();
+        locals.resize(declaration.locals.len(), Variable::new(Value::Int(0)));
+
+        self.frames.push(Frame {
+            function,
+            ip: 0,
+            locals,
+        });
+    }
+}
@@ -0,0 +1,64 @@
+const INDENT: &str = "    ";
+
+// Builds code line by line, indenting every line by the nesting depth of
+// the blocks around it. Used for the generated C and by the formatter.
+// Lines are never broken, so a long expression stays on one line: breaking
+// it would throw off the line numbers #line directives set for the lines
+// after it
+#[derive(Default)]
+pub struct CodeWriter {
+    output: String,
+    depth: usize,
+}
+
+impl CodeWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn line(&mut self, line: &str) {
+        for _ in 0..self.depth {
+            self.output += INDENT;
+        }
+        self.output += line;
+        self.output += "\n";
+    }
+
+    // Preprocessor directives always start at the beginning of the line
+    pub fn directive(&mut self, directive: &str) {
+        self.output += directive;
+        self.output += "\n";
+    }
+
+    pub fn blank_line(&mut self) {
+        self.output += "\n";
+    }
+
+    pub fn indent(&mut self) {
+        self.depth += 1;
+    }
+
+    pub fn dedent(&mut self) {
+        self.depth = self
+            .depth
+            .checked_sub(1)
+            .expect("dedented more often than indented");
+    }
+
+    // Writes `header {`, or just `{` without a header, then the body one
+    // level deeper and the closing brace
+    pub fn block(&mut self, header: &str, body: impl FnOnce(&mut Self)) {
+        match header {
+            "" => self.line("{"),
+            header => self.line(&format!("{} {{", header)),
+        }
+        self.indent();
+        body(self);
+        self.dedent();
+        self.line("}");
+    }
+
+    pub fn finish(self) -> String {
+        self.output
+    }
+}
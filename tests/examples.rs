@@ -0,0 +1,160 @@
+// Runs every example through each backend and compares what it prints with
+// the expected output in tests/examples. The interpreter and the VM always
+// run, the C and assembly backends only when there is a C compiler to build
+// their binaries with
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+const TRUNKPP: &str = env!("CARGO_BIN_EXE_trunkpp");
+
+// bnf_test shows off the error messages, so it never gets to run
+const SKIPPED: &[&str] = &["bnf_test"];
+
+struct Example {
+    name: String,
+    path: PathBuf,
+    input: String,
+    expected: String,
+}
+
+fn examples() -> Vec<Example> {
+    let mut examples = Vec::new();
+    for entry in fs::read_dir("examples").expect("the examples directory exists") {
+        let path = entry.expect("the examples directory can be read").path();
+        if path.extension().and_then(|extension| extension.to_str()) != Some("tpp") {
+            continue;
+        }
+
+        let name = path.file_stem().unwrap().to_string_lossy().to_string();
+        if SKIPPED.contains(&name.as_str()) {
+            continue;
+        }
+
+        let expected = fs::read_to_string(format!("tests/examples/{}.out", name))
+            .unwrap_or_else(|_| panic!("tests/examples/{}.out is missing", name));
+        let input = fs::read_to_string(format!("tests/examples/{}.in", name)).unwrap_or_default();
+        examples.push(Example {
+            name,
+            path,
+            input,
+            expected,
+        });
+    }
+
+    examples.sort_by(|a, b| a.name.cmp(&b.name));
+    examples
+}
+
+fn run(command: &mut Command, input: &str) -> Output {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("the command can be started");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .expect("the input can be written");
+    child.wait_with_output().expect("the command finishes")
+}
+
+fn assert_output(example: &Example, backend: &str, output: Output) {
+    assert!(
+        output.status.success(),
+        "{} failed with {} under {}\n{}",
+        example.name,
+        output.status,
+        backend,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        example.expected,
+        "{} printed something else under {}",
+        example.name,
+        backend
+    );
+}
+
+fn has_c_compiler() -> bool {
+    Command::new("cc")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+#[test]
+fn interpreter_runs_the_examples() {
+    for example in examples() {
+        let output = run(
+            Command::new(TRUNKPP).arg("interpret").arg(&example.path),
+            &example.input,
+        );
+        assert_output(&example, "interpret", output);
+    }
+}
+
+#[test]
+fn vm_runs_the_examples() {
+    for example in examples() {
+        let output = run(
+            Command::new(TRUNKPP).arg("vm").arg(&example.path),
+            &example.input,
+        );
+        assert_output(&example, "vm", output);
+    }
+}
+
+#[test]
+fn c_backend_runs_the_examples() {
+    if !has_c_compiler() {
+        eprintln!("skipped: no C compiler");
+        return;
+    }
+
+    for example in examples() {
+        let output = run(
+            Command::new(TRUNKPP).arg("run").arg(&example.path),
+            &example.input,
+        );
+        assert_output(&example, "run", output);
+    }
+}
+
+#[test]
+fn assembly_backend_runs_the_examples() {
+    if !has_c_compiler() {
+        eprintln!("skipped: no C compiler");
+        return;
+    }
+
+    let directory = std::env::temp_dir().join(format!("trunkpp-examples-{}", std::process::id()));
+    fs::create_dir_all(&directory).expect("the temporary directory can be created");
+    for example in examples() {
+        let binary = directory.join(&example.name);
+        let built = Command::new(TRUNKPP)
+            .arg("assemble")
+            .arg(&example.path)
+            .arg("-o")
+            .arg(&binary)
+            .output()
+            .expect("trunkpp can be started");
+        assert!(
+            built.status.success(),
+            "{} did not assemble\n{}",
+            example.name,
+            String::from_utf8_lossy(&built.stderr)
+        );
+
+        let output = run(&mut Command::new(&binary), &example.input);
+        assert_output(&example, "assemble", output);
+    }
+    let _ = fs::remove_dir_all(&directory);
+}